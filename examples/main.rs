@@ -14,20 +14,32 @@ use renderer::Renderer;
 fn main() {
 
     let seed: &[_] = &[1, 2, 3, 8];
-    let dungeon = dungeon::Dungeon::from_seed(seed, 19, 10);
+    let dungeon = if env::args().any(|arg| arg == "--mission") {
+        dungeon::Dungeon::from_seed_with_mission(seed, dungeon::Dungeon::DEFAULT_ROOM_COUNT, 10)
+
+    } else {
+        dungeon::Dungeon::from_seed(seed, dungeon::Dungeon::DEFAULT_ROOM_COUNT, 10)
+    };
 
     let render_type = env::args().skip(1).next().unwrap_or("ascii".into());
+    let show_secrets = env::args().any(|arg| arg == "--hide-secrets") == false;
 
     if let Some(dungeon) = dungeon {
 
+        println!("Successfully generated dungeon on try #{} !", dungeon.tries());
+
         if let Some(renderer) = match render_type.as_ref() {
-            "allegro" => renderer::AllegroRenderer::from_dungeon(&dungeon),
-            _ => renderer::AsciiRenderer::from_dungeon(&dungeon)
+            "allegro" => renderer::AllegroRenderer::from_dungeon(&dungeon, show_secrets),
+            _ => renderer::AsciiRenderer::from_dungeon(&dungeon, show_secrets)
 
         } {
             renderer.draw();
         }
 
+        if env::args().any(|arg| arg == "--metrics") {
+            println!("{}", dungeon.metrics().to_string());
+        }
+
     }
 
 }
@@ -19,7 +19,7 @@ pub struct AllegroRenderer {
 
 impl Renderer for AllegroRenderer {
 
-    fn from_dungeon(dungeon: &dungeon::Dungeon) -> Option<Box<Renderer>> {
+    fn from_dungeon(dungeon: &dungeon::Dungeon, _: bool) -> Option<Box<Renderer>> {
 
         // Calculate bounds
         let mut min = Offset { x: 9999, y: 9999 };
@@ -4,6 +4,7 @@ use renderer::Renderer;
 use dungeon;
 use dungeon::base::{Offset, Side};
 use dungeon::room;
+use dungeon::entity::Entity;
 
 pub struct AsciiRenderer {
     sx: usize,
@@ -15,7 +16,7 @@ pub struct AsciiRenderer {
 
 impl Renderer for AsciiRenderer {
 
-    fn from_dungeon(dungeon: &dungeon::Dungeon) -> Option<Box<Renderer>> {
+    fn from_dungeon(dungeon: &dungeon::Dungeon, show_secrets: bool) -> Option<Box<Renderer>> {
 
         // Print Statistics
         println!("Dungeon with {} rooms", dungeon.rooms.len());
@@ -48,7 +49,9 @@ impl Renderer for AsciiRenderer {
 
             // Draw rooms into buffer
             for (_, room) in dungeon.rooms.iter() {
-                renderer.draw_room(room);
+                if show_secrets || room.typ != room::Type::Secret {
+                    renderer.draw_room(dungeon, room, show_secrets);
+                }
             }
 
             Some(Box::new(renderer))
@@ -93,7 +96,7 @@ impl AsciiRenderer {
 
     }
 
-    fn draw_room(&mut self, room: &room::Room) {
+    fn draw_room(&mut self, dungeon: &dungeon::Dungeon, room: &room::Room, show_secrets: bool) {
 
         let x = room.offset.x as usize;
         let y = room.offset.y as usize;
@@ -111,17 +114,28 @@ impl AsciiRenderer {
         self.buffer[(y * sy + sy - 2) * self.width * self.sx + x * sx] = '\u{2517}';
         self.buffer[(y * sy + sy - 2) * self.width * self.sx + x * sx + sx - 3] = '\u{251b}';
 
+        // Shade dark rooms
+        if room.dark {
+            for ry in y * sy + 1..y * sy + sy - 2 {
+                self.draw_hline(ry, x * sx + 1, x * sx + sx - 3, '\u{2591}');
+            }
+        }
+
         // Doors
         for d in room.doors.iter() {
+            let lock = &dungeon.door(d.door).unwrap().lock;
+            if show_secrets == false && *lock == room::door::Lock::Hidden {
+                continue;
+            }
             self.draw_door(
-                room.offset.x, room.offset.y, &d.side, d.lock.to_char()
+                room.offset.x, room.offset.y, &d.side, lock.to_char()
             );
         }
 
         // Room Types
         let mut y_offset = 2;
         match room.typ {
-            room::Type::Exit | room::Type::Entrance => {
+            room::Type::Exit | room::Type::Entrance | room::Type::Secret => {
                 self.draw_text(
                     room.offset.x, room.offset.y,
                     1, y_offset,
@@ -132,33 +146,45 @@ impl AsciiRenderer {
             _ => {}
         }
 
-        // Chest
-        if let Some(ref chest) = room.chest {
-            self.draw_text(
-                room.offset.x, room.offset.y,
-                1, y_offset,
-                &chest.to_string()[..]
-            );
-            y_offset += 1;
+        // Entities, with all torches, pots and traps grouped into a single line
+        let mut lines: Vec<String> = room.entities.iter().filter(|&&(_, ref e)| {
+            match *e {
+                Entity::Torch(_) | Entity::Pot(_) | Entity::Trap(_) => false,
+                _ => true
+            }
+
+        }).map(|&(_, ref e)| e.to_string()).collect();
+
+        let torches = room.torches();
+        if torches.len() > 0 {
+            lines.push(torches.iter().map(|&(_, t)| t.to_char()).collect());
         }
 
-        // Enemy
-        if let Some(ref enemy) = room.enemy {
-            self.draw_text(
-                room.offset.x, room.offset.y,
-                1, y_offset,
-                &enemy.to_string()[..]
-            );
-            y_offset += 1;
+        let pots = room.pots();
+        if pots.len() > 0 {
+            lines.push(pots.iter().map(|&(_, p)| p.to_string()).collect::<Vec<String>>().join(" "));
         }
 
-        // Switch
-        if let Some(ref switch) = room.switch {
-            self.draw_text(
-                room.offset.x, room.offset.y,
-                1, y_offset,
-                &switch.to_string()[..]
-            );
+        let traps = room.traps();
+        if traps.len() > 0 {
+            lines.push(traps.iter().map(|&(_, t)| t.to_string()).collect::<Vec<String>>().join(" "));
+        }
+
+        // Trigger Sets
+        for set in room.trigger_sets.iter() {
+            lines.push(set.to_string());
+        }
+
+        // Only draw as many lines as fit into the room
+        for line in lines.iter() {
+            if y_offset < self.sy - 2 {
+                self.draw_text(
+                    room.offset.x, room.offset.y,
+                    1, y_offset,
+                    &line[..]
+                );
+                y_offset += 1;
+            }
         }
 
     }
@@ -198,7 +224,8 @@ impl AsciiRenderer {
         let (x, y) = (x as usize, y as usize);
         let (sx, sy) = (self.sx, self.sy);
 
-        for (index, t) in text.chars().enumerate() {
+        // Clip the text at the right wall of the room, which is drawn at sx - 3
+        for (index, t) in text.chars().take((sx - 3).saturating_sub(ox)).enumerate() {
             self.buffer[(y * sy + oy) * self.width * self.sx + x * sx + ox + index] = t;
         }
 
@@ -1,7 +1,7 @@
 use dungeon;
 
 pub trait Renderer {
-    fn from_dungeon(dungeon: &dungeon::Dungeon) -> Option<Box<Renderer>> where Self: Sized;
+    fn from_dungeon(dungeon: &dungeon::Dungeon, show_secrets: bool) -> Option<Box<Renderer>> where Self: Sized;
     fn draw(&self);
 }
 
@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::ops::{Add, Sub};
 
 // TODO support up / down
@@ -59,6 +60,15 @@ impl Side {
 
 }
 
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct RoomId(pub usize);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct DoorId(pub usize);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct EntityId(pub usize);
+
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub struct Offset {
     pub x: i32,
@@ -74,6 +84,27 @@ impl Default for Offset {
     }
 }
 
+// Offsets are ordered row by row, from the top left to the bottom right
+impl Ord for Offset {
+    fn cmp(&self, other: &Offset) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Offset {
+    fn partial_cmp(&self, other: &Offset) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// HashMap iteration order is random, but generation needs to be
+// deterministic, so any offsets collected from one are sorted by position
+pub fn sorted_offsets<I>(offsets: I) -> Vec<Offset> where I : IntoIterator<Item=Offset> {
+    let mut offsets: Vec<Offset> = offsets.into_iter().collect();
+    offsets.sort();
+    offsets
+}
+
 impl Add for Offset {
     type Output = Offset;
 
@@ -0,0 +1,157 @@
+use std::cmp;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use dungeon::Dungeon;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Error {
+    Failed,
+    Panicked(String)
+}
+
+impl Error {
+    pub fn to_string(&self) -> String {
+        match *self {
+            Error::Failed => {
+                "failed to generate a dungeon".to_owned()
+            },
+            Error::Panicked(ref message) => {
+                format!("generation panicked: {}", message)
+            }
+        }
+    }
+}
+
+// The outcome of generating the dungeon for one of the seeds of a batch
+pub struct Generated {
+    pub index: usize,
+    pub seed: Vec<usize>,
+    pub dungeon: Result<Dungeon, Error>
+}
+
+// Generates dungeons for a list of seeds on a number of worker threads,
+// results are streamed back in the order they finish
+pub struct Batch {
+    receiver: Receiver<Generated>,
+    workers: Vec<thread::JoinHandle<()>>
+}
+
+impl Batch {
+
+    pub fn from_seeds(
+        seeds: Vec<Vec<usize>>, room_count: usize, max_tries: usize, threads: usize
+
+    ) -> Batch {
+        Batch::with(seeds, threads, move |seed| {
+            Dungeon::from_seed(seed, room_count, max_tries)
+        })
+    }
+
+    pub fn with<F>(
+        seeds: Vec<Vec<usize>>, threads: usize, generate: F
+
+    ) -> Batch where F : Fn(&[usize]) -> Option<Dungeon> + Send + Sync + 'static {
+
+        let (sender, receiver) = channel();
+        let seeds = Arc::new(seeds);
+        let next = Arc::new(Mutex::new(0));
+        let generate = Arc::new(generate);
+
+        // Every worker keeps taking the next seed until none are left, each
+        // dungeon is generated from its own RNG so the results only depend on
+        // the seed and not on which thread picked it up
+        let workers = (0..cmp::max(threads, 1)).map(|_| {
+
+            let sender = sender.clone();
+            let seeds = seeds.clone();
+            let next = next.clone();
+            let generate = generate.clone();
+
+            thread::spawn(move || {
+                loop {
+
+                    let index = {
+                        let mut next = next.lock().unwrap();
+                        let index = *next;
+                        *next += 1;
+                        index
+                    };
+
+                    if index >= seeds.len() {
+                        break;
+                    }
+
+                    // A panic only fails its own seed instead of taking the
+                    // worker and every seed it would still pick up with it
+                    let seed = seeds[index].clone();
+                    let dungeon = match panic::catch_unwind(AssertUnwindSafe(|| generate(&seed))) {
+                        Ok(Some(dungeon)) => Ok(dungeon),
+                        Ok(None) => Err(Error::Failed),
+                        Err(payload) => {
+                            let message = if let Some(message) = payload.downcast_ref::<&str>() {
+                                message.to_string()
+
+                            } else if let Some(message) = payload.downcast_ref::<String>() {
+                                message.clone()
+
+                            } else {
+                                "unknown panic".to_owned()
+                            };
+                            Err(Error::Panicked(message))
+                        }
+                    };
+
+                    let generated = Generated {
+                        index: index,
+                        seed: seed,
+                        dungeon: dungeon
+                    };
+
+                    // Stop early in case the batch was dropped
+                    if sender.send(generated).is_err() {
+                        break;
+                    }
+
+                }
+            })
+
+        }).collect();
+
+        Batch {
+            receiver: receiver,
+            workers: workers
+        }
+
+    }
+
+    // Waits for all remaining dungeons and returns them in seed order
+    pub fn collect_ordered(self) -> Vec<Generated> {
+        let mut generated: Vec<Generated> = self.collect();
+        generated.sort_by(|a, b| a.index.cmp(&b.index));
+        generated
+    }
+
+}
+
+impl Iterator for Batch {
+
+    type Item = Generated;
+
+    fn next(&mut self) -> Option<Generated> {
+        match self.receiver.recv() {
+            Ok(generated) => Some(generated),
+            Err(_) => {
+                // All workers are done once the channel is closed
+                for worker in self.workers.drain(..) {
+                    worker.join().ok();
+                }
+                None
+            }
+        }
+    }
+
+}
+
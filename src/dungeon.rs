@@ -1,31 +1,485 @@
 use std::cmp;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 use rand::{Rng, SeedableRng, StdRng};
 
-use base::{Side, Offset};
+use base::{Side, Offset, RoomId, DoorId, EntityId, sorted_offsets};
 use room::{Room, Path as RoomPath, Type as RoomType};
 use room::door::{Door, Lock as DoorLock};
+use entity::Entity;
 use entity::chest::Chest;
+use entity::crystal::{Crystal, Color};
 use entity::item::{Item, Key};
 use entity::enemy::{Enemy, Type as EnemyType};
+use entity::pot::Pot;
 use entity::switch::Switch;
-use entity::trigger::Trigger;
+use entity::torch::Torch;
+use entity::trap::{Trap, Type as TrapType};
+use entity::trigger::{Trigger, TriggerSet, Condition};
+use entity::warp::Warp;
+use state::{State, Environment, Change};
+use mission::{Mission, Node as MissionNode, Error as MissionError};
+use graph::Graph;
+use metrics::{Metrics, Targets, Candidate, Error as TargetsError};
+use session::{Session, Phase, Difficulty, CancelToken, Error as SessionError};
+use evolution::{self, Layout, Individual};
+
+enum Progress {
+    Interacted,
+    Collected(Key),
+    Unlocked(DoorId),
+    Exited
+}
+
+type Pass = fn(&mut Dungeon, &mut StdRng) -> bool;
+
+// Reports the generation progress of a session and tells the passes when to
+// stop, plain generation just runs without one
+struct Monitor<'a> {
+    progress: Option<&'a mut dyn FnMut(Phase, usize)>,
+    tries: usize,
+    started: Instant,
+    budget: Option<Duration>,
+    cancel: Option<CancelToken>
+}
+
+impl<'a> Monitor<'a> {
+
+    fn none() -> Monitor<'a> {
+        Monitor {
+            progress: None,
+            tries: 0,
+            started: Instant::now(),
+            budget: None,
+            cancel: None
+        }
+    }
+
+    fn enter(&mut self, phase: Phase) -> bool {
+        if self.stopped().is_some() {
+            false
+
+        } else {
+            if let Some(ref mut progress) = self.progress {
+                progress(phase, self.tries);
+            }
+            true
+        }
+    }
+
+    fn stopped(&self) -> Option<SessionError> {
+        if self.cancel.as_ref().map_or(false, |cancel| cancel.is_cancelled()) {
+            Some(SessionError::Cancelled)
+
+        } else if self.budget.map_or(false, |budget| self.started.elapsed() >= budget) {
+            Some(SessionError::OutOfTime)
+
+        } else {
+            None
+        }
+    }
+
+}
+
+#[derive(Clone)]
+struct Ids {
+    next: usize
+}
+
+impl Ids {
+
+    fn next(&mut self) -> usize {
+        self.next += 1;
+        self.next
+    }
+
+    fn room(&mut self) -> RoomId {
+        RoomId(self.next())
+    }
+
+    fn door(&mut self) -> DoorId {
+        DoorId(self.next())
+    }
+
+    fn entity(&mut self) -> EntityId {
+        EntityId(self.next())
+    }
+
+}
+
+// Rooms which can be picked at random and removed again in constant time
+struct RoomPool {
+    rooms: Vec<Offset>,
+    indices: HashMap<Offset, usize>
+}
+
+impl RoomPool {
+
+    fn new() -> RoomPool {
+        RoomPool {
+            rooms: Vec::new(),
+            indices: HashMap::new()
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.rooms.len()
+    }
+
+    fn insert(&mut self, offset: Offset) {
+        if self.indices.contains_key(&offset) == false {
+            self.indices.insert(offset, self.rooms.len());
+            self.rooms.push(offset);
+        }
+    }
+
+    fn remove(&mut self, offset: Offset) -> bool {
+        match self.indices.remove(&offset) {
+            Some(index) => {
+                self.rooms.swap_remove(index);
+                if index < self.rooms.len() {
+                    self.indices.insert(self.rooms[index], index);
+                }
+                true
+            },
+            None => false
+        }
+    }
+
+    fn pick(&mut self, rng: &mut StdRng) -> Option<Offset> {
+        if self.rooms.len() > 0 {
+            let offset = self.rooms[rng.gen_range(0, self.rooms.len())];
+            self.remove(offset);
+            Some(offset)
+
+        } else {
+            None
+        }
+    }
+
+}
+
+// Breadth first search tree of the rooms reachable from a start room, with
+// the room each one was first reached from
+struct RoomTree {
+    parents: HashMap<Offset, Offset>,
+    order: HashMap<Offset, usize>,
+    depths: HashMap<Offset, usize>
+}
+
+impl RoomTree {
+
+    fn path(&self, offset: Offset) -> RoomPath {
+
+        let mut path = RoomPath::new();
+        path.push(offset);
+
+        // Walk back up to the start room which is its own parent
+        let mut current = offset;
+        while let Some(parent) = self.parents.get(&current).cloned() {
+            if parent == current {
+                break;
+            }
+            path.push(parent);
+            current = parent;
+        }
+
+        path.reverse();
+        path
+
+    }
+
+    fn is_on_path(&self, offset: Offset, to: Offset) -> bool {
+
+        let depth = match self.depths.get(&offset) {
+            Some(depth) => *depth,
+            None => return false
+        };
+
+        // Rooms can only lie on the path if they are not any deeper
+        let mut current = to;
+        loop {
+            if current == offset {
+                return true;
+            }
+
+            match self.parents.get(&current) {
+                Some(parent) if *parent != current && *self.depths.get(parent).unwrap() >= depth => {
+                    current = *parent;
+                },
+                _ => return false
+            }
+        }
+
+    }
+
+}
 
+#[derive(Clone)]
 pub struct Dungeon {
     entrance_room: Option<Offset>,
     boss_room: Option<Offset>,
+    miniboss_room: Option<Offset>,
     exit_room: Option<Offset>,
-    pub rooms: HashMap<Offset, Room>
+    tries: usize,
+    difficulty: Difficulty,
+    ids: Ids,
+    room_ids: HashMap<RoomId, Offset>,
+    pub rooms: HashMap<Offset, Room>,
+    pub doors: HashMap<DoorId, Door>
 }
 
 impl Dungeon {
 
     // Statics ----------------------------------------------------------------
 
+    // Number of rooms to generate when nothing else asks for a specific size
+    pub const DEFAULT_ROOM_COUNT: usize = 19;
+
+    // Smaller dungeons cannot hold the entrance, exit and boss key rooms,
+    // while larger ones take too long to generate for searching through them
+    pub const MIN_ROOM_COUNT: usize = 3;
+    pub const MAX_ROOM_COUNT: usize = 100000;
+
     pub fn from_seed(
         seed: &[usize], room_count: usize, max_tries: usize
 
     ) -> Option<Dungeon> {
+        Dungeon::from_seed_with(seed, max_tries, |dungeon, rng, monitor| {
+            dungeon.generate(rng, room_count, monitor)
+        })
+    }
+
+    pub fn from_seed_with_mission(
+        seed: &[usize], room_count: usize, max_tries: usize
+
+    ) -> Option<Dungeon> {
+        Dungeon::from_seed_with(seed, max_tries, |dungeon, rng, monitor| {
+            let mission = Mission::generate(rng, room_count);
+            dungeon.generate_from_mission(rng, &mission, monitor)
+        })
+    }
+
+    pub fn from_mission(
+        seed: &[usize], mission: &Mission, max_rooms: usize, max_tries: usize
+
+    ) -> Result<Dungeon, MissionError> {
+
+        if let Err(err) = mission.check(max_rooms) {
+            return Err(err);
+        }
+
+        // Only the layout is random, all locks and keys come from the mission
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut error = MissionError::NoLayout;
+        for tries in 1..max_tries + 1 {
+
+            let mut dungeon = Dungeon::new();
+            dungeon.tries = tries;
+            match dungeon.embed_mission(&mut rng, mission) {
+                Ok(()) => {
+                    if dungeon.validate() {
+                        return Ok(dungeon);
+
+                    } else {
+                        error = MissionError::Unsolvable;
+                    }
+                },
+                Err(err) => {
+                    error = err;
+                }
+            }
+
+        }
+
+        Err(error)
+
+    }
+
+    pub fn from_targets(
+        seed: &[usize], targets: &Targets, max_attempts: usize, max_duration: Duration
+
+    ) -> Result<Candidate, TargetsError> {
+
+        if let Err(err) = targets.check() {
+            return Err(err);
+        }
+
+        let start = Instant::now();
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut best: Option<Candidate> = None;
+        for _ in 0..max_attempts {
+
+            if start.elapsed() >= max_duration {
+                break;
+            }
+
+            // Every candidate gets its own seed so it can be reproduced later
+            // on, open ended room ranges stay close to their lower end
+            let candidate_seed: Vec<usize> = (0..4).map(|_| rng.gen_range(0, 1000000)).collect();
+            let room_count = match targets.rooms {
+                Some((min, max)) => {
+                    let min = cmp::max(min, Dungeon::MIN_ROOM_COUNT);
+                    let max = cmp::min(cmp::min(max, Dungeon::MAX_ROOM_COUNT), min.saturating_mul(2));
+                    rng.gen_range(min, max + 1)
+                },
+                None => Dungeon::DEFAULT_ROOM_COUNT
+            };
+
+            if let Some(dungeon) = Dungeon::from_seed(&candidate_seed, room_count, 10) {
+
+                let metrics = dungeon.metrics();
+                let score = targets.score(&metrics);
+                if best.as_ref().map_or(true, |candidate| score < candidate.score) {
+                    best = Some(Candidate {
+                        seed: candidate_seed,
+                        dungeon: dungeon,
+                        metrics: metrics,
+                        score: score
+                    });
+                }
+
+                if score == 0.0 {
+                    break;
+                }
+
+            }
+
+        }
+
+        best.ok_or(TargetsError::NotFound)
+
+    }
+
+    pub fn from_layout(
+        seed: &[usize], layout: &Layout, max_tries: usize
+
+    ) -> Option<Dungeon> {
+        Dungeon::from_seed_with(seed, max_tries, |dungeon, rng, monitor| {
+            dungeon.generate_from_layout(rng, layout, monitor)
+        })
+    }
+
+    pub fn evolve<F>(
+        seed: &[usize], room_count: usize, population: usize, generations: usize, fitness: F
+
+    ) -> Option<Individual> where F : Fn(&Dungeon, &Metrics) -> f32 {
+
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+
+        // Start out with randomly generated layouts
+        let mut individuals: Vec<Individual> = Vec::new();
+        let mut attempts = 0;
+        while individuals.len() < population && attempts < population * 10 {
+
+            let mut dungeon = Dungeon::new();
+            dungeon.create_rooms(&mut rng, room_count);
+
+            let layout = Layout::from_dungeon(&dungeon);
+            if let Some(individual) = Dungeon::evaluate(&mut rng, layout, &fitness) {
+                individuals.push(individual);
+            }
+
+            attempts += 1;
+
+        }
+
+        for _ in 0..generations {
+
+            // Every individual gets one offspring, a copy of itself with a
+            // single mutation applied on top of its placement
+            let mut offspring = Vec::new();
+            for individual in individuals.iter() {
+                if let Some(child) = Dungeon::offspring(&mut rng, individual, &fitness) {
+                    offspring.push(child);
+                }
+            }
+
+            // Keep the fittest, parents win ties against their offspring
+            individuals.extend(offspring);
+            individuals.sort_by(|a, b| {
+                b.fitness.partial_cmp(&a.fitness).unwrap_or(Ordering::Equal)
+            });
+            individuals.truncate(population);
+
+        }
+
+        individuals.into_iter().next()
+
+    }
+
+    fn evaluate<F>(
+        rng: &mut StdRng, layout: Layout, fitness: &F
+
+    ) -> Option<Individual> where F : Fn(&Dungeon, &Metrics) -> f32 {
+
+        let seed: Vec<usize> = (0..4).map(|_| rng.gen_range(0, 1000000)).collect();
+        Dungeon::from_layout(&seed, &layout, 1).map(|dungeon| {
+            let metrics = dungeon.metrics();
+            let fitness = fitness(&dungeon, &metrics);
+            Individual {
+                layout: layout,
+                seed: seed,
+                dungeon: dungeon,
+                metrics: metrics,
+                fitness: fitness
+            }
+        })
+
+    }
+
+    fn offspring<F>(
+        rng: &mut StdRng, parent: &Individual, fitness: &F
+
+    ) -> Option<Individual> where F : Fn(&Dungeon, &Metrics) -> f32 {
+
+        // Mutations can make the dungeon unsolvable, in which case the
+        // offspring is discarded
+        let mut dungeon = parent.dungeon.clone();
+        if evolution::mutate(&mut dungeon, rng) == false || dungeon.validate() == false {
+            return None;
+        }
+
+        let metrics = dungeon.metrics();
+        let fitness = fitness(&dungeon, &metrics);
+        Some(Individual {
+            layout: Layout::from_dungeon(&dungeon),
+            seed: parent.seed.clone(),
+            dungeon: dungeon,
+            metrics: metrics,
+            fitness: fitness
+        })
+
+    }
+
+    pub fn from_session<F>(session: &Session, mut progress: F) -> Result<Dungeon, SessionError> where F : FnMut(Phase, usize) {
+
+        let mut monitor = Monitor::none();
+        monitor.progress = Some(&mut progress);
+        monitor.budget = session.budget;
+        monitor.cancel = Some(session.cancel.clone());
+
+        let room_count = session.room_count;
+        let difficulty = session.difficulty;
+        Dungeon::from_seed_monitored(&session.seed, session.max_tries, &mut monitor, |dungeon, rng, monitor| {
+            dungeon.difficulty = difficulty;
+            dungeon.generate(rng, room_count, monitor)
+        })
+
+    }
+
+    fn from_seed_with<F>(
+        seed: &[usize], max_tries: usize, generate: F
+
+    ) -> Option<Dungeon> where F : Fn(&mut Dungeon, &mut StdRng, &mut Monitor) -> bool {
+        Dungeon::from_seed_monitored(seed, max_tries, &mut Monitor::none(), generate).ok()
+    }
+
+    fn from_seed_monitored<F>(
+        seed: &[usize], max_tries: usize, monitor: &mut Monitor, generate: F
+
+    ) -> Result<Dungeon, SessionError> where F : Fn(&mut Dungeon, &mut StdRng, &mut Monitor) -> bool {
 
         let mut rng: StdRng = SeedableRng::from_seed(seed);
         let mut tries = 0;
@@ -33,54 +487,195 @@ impl Dungeon {
         while tries < max_tries {
 
             tries += 1;
+            monitor.tries = tries;
 
-            let mut dungeon = Dungeon {
-                entrance_room: None,
-                exit_room: None,
-                boss_room: None,
-                rooms: HashMap::new()
-            };
+            let mut dungeon = Dungeon::new();
+            dungeon.tries = tries;
+            if generate(&mut dungeon, &mut rng, monitor) == false {
 
-            if dungeon.generate(&mut rng, room_count) == false {
-                println!("Failed to generate dungeon on try #{}", tries);
-                continue;
+                // Passes bail out early once the monitor stopped
+                if let Some(err) = monitor.stopped() {
+                    return Err(err);
+                }
 
             } else {
-                println!("Successfully generated dungeon on try #{} !", tries);
-                return Some(dungeon);
+                return Ok(dungeon);
             }
 
         }
 
-        None
+        Err(SessionError::Failed(max_tries))
 
     }
 
 
-    // Generation Methods -----------------------------------------------------
+    fn new() -> Dungeon {
+        Dungeon {
+            entrance_room: None,
+            exit_room: None,
+            tries: 0,
+            difficulty: Difficulty::Normal,
+            boss_room: None,
+            miniboss_room: None,
+            ids: Ids {
+                next: 0
+            },
+            room_ids: HashMap::new(),
+            rooms: HashMap::new(),
+            doors: HashMap::new()
+        }
+    }
+
+
+    // Public Interface -------------------------------------------------------
+
+    // Number of the try on which the dungeon was generated
+    pub fn tries(&self) -> usize {
+        self.tries
+    }
+
+    pub fn room(&self, id: RoomId) -> Option<&Room> {
+        match self.room_ids.get(&id) {
+            Some(offset) => self.rooms.get(offset),
+            None => None
+        }
+    }
+
+    pub fn door(&self, id: DoorId) -> Option<&Door> {
+        self.doors.get(&id)
+    }
+
+    pub fn door_between(&self, a: Offset, b: Offset) -> Option<&Door> {
+        match self.rooms.get(&a).and_then(|room| room.connection_to(&b)) {
+            Some(connection) => self.doors.get(&connection.door),
+            None => None
+        }
+    }
+
+    pub fn entity(&self, id: EntityId) -> Option<&Entity> {
+        self.rooms.values().filter_map(|room| room.entity(id)).next()
+    }
+
 
-    fn generate(&mut self, rng: &mut StdRng, max_rooms: usize) -> bool {
+    // Generation Methods -----------------------------------------------------
 
+    fn generate(&mut self, rng: &mut StdRng, max_rooms: usize, monitor: &mut Monitor) -> bool {
+        if monitor.enter(Phase::Layout) == false {
+            return false;
+        }
         self.create_rooms(rng, max_rooms);
+        self.place_progression(rng, monitor)
+    }
 
-        if self.set_special_rooms(rng) == false {
+    fn generate_from_layout(&mut self, rng: &mut StdRng, layout: &Layout, monitor: &mut Monitor) -> bool {
+        if monitor.enter(Phase::Layout) == false {
             return false;
+        }
+        self.create_layout_rooms(layout);
+        self.place_progression(rng, monitor)
+    }
 
-        } else if self.set_locked_doors(rng) == false {
+    fn place_progression(&mut self, rng: &mut StdRng, monitor: &mut Monitor) -> bool {
+
+        if monitor.enter(Phase::SpecialRooms) == false || self.set_special_rooms(rng) == false {
             return false;
+        }
+
+        // The dungeon cannot be walked through until its small keys are placed
+        let mut passes: Vec<(Pass, Phase, bool)> = vec![
+            (Dungeon::set_locked_doors, Phase::Locks, false),
+            (Dungeon::set_locked_keys, Phase::Keys, true),
+            (Dungeon::set_item_gates, Phase::Locks, true)
+        ];
+        passes.extend(Dungeon::finishing_passes());
+        self.run_passes(rng, &passes, monitor)
 
-        } else if self.set_locked_keys(rng) == false {
+    }
+
+    fn generate_from_mission(&mut self, rng: &mut StdRng, mission: &Mission, monitor: &mut Monitor) -> bool {
+        if monitor.enter(Phase::Layout) == false {
             return false;
+        }
+        match self.embed_mission(rng, mission) {
+            Ok(()) => self.finish(rng, monitor),
+            Err(err) => {
+                println!("Failed to embed mission: {}", err.to_string());
+                false
+            }
+        }
+    }
+
+    fn finish(&mut self, rng: &mut StdRng, monitor: &mut Monitor) -> bool {
+        self.run_passes(rng, &Dungeon::finishing_passes(), monitor)
+    }
+
+    fn finishing_passes() -> Vec<(Pass, Phase, bool)> {
+        // TODO place compass / map in chests
+        // TODO place enemies in rooms
+        // TODO place other door locks
+        vec![
+            (Dungeon::set_warps, Phase::Features, true),
+            (Dungeon::set_barriers, Phase::Features, true),
+            (Dungeon::set_water_levels, Phase::Features, true),
+            (Dungeon::set_dark_rooms, Phase::Features, true),
+            (Dungeon::set_shortcuts, Phase::Features, true),
+            (Dungeon::set_secret_rooms, Phase::Features, true),
+            (Dungeon::set_traps, Phase::Features, true)
+        ]
+    }
+
+    fn run_passes(&mut self, rng: &mut StdRng, passes: &[(Pass, Phase, bool)], monitor: &mut Monitor) -> bool {
+
+        let max_attempts = 3;
+        let mut max_failures = 30;
+
+        // Run the passes in order, a failed pass is retried from a snapshot of
+        // the dungeon before it ran and once it keeps failing we backtrack
+        // into the pass before it, instead of throwing away the whole dungeon
+        let mut snapshots: Vec<Dungeon> = Vec::new();
+        let mut attempts = vec![0; passes.len()];
+        let mut index = 0;
+        while index < passes.len() {
+
+            if snapshots.len() == index {
+                snapshots.push(self.clone());
+            }
+
+            // Stop in between passes, they cannot be interrupted while running
+            let (pass, phase, validate) = passes[index];
+            if monitor.enter(phase) == false {
+                return false;
+            }
+
+            if pass(self, rng) && (validate == false || (monitor.enter(Phase::Validation) && self.validate())) {
+                index += 1;
+                if index < passes.len() {
+                    attempts[index] = 0;
+                }
+                continue;
+            }
+
+            attempts[index] += 1;
+            max_failures -= 1;
+            if max_failures == 0 {
+                println!("Failed to repair dungeon, too many failed passes");
+                return false;
+
+            } else if attempts[index] == max_attempts {
+                if index == 0 {
+                    println!("Failed to repair dungeon, ran out of passes to backtrack into");
+                    return false;
+                }
+                snapshots.pop();
+                index -= 1;
+            }
+
+            *self = snapshots[index].clone();
 
-        } else {
-            // TODO place compass / map in chests
-            // TODO place enemies in rooms
-            // TODO place other door locks
-            // TODO Create some additional shortcuts between rooms (both rooms need to reach each
-            // other without requiring small keys)
-            true
         }
 
+        true
+
     }
 
     fn create_rooms(&mut self, rng: &mut StdRng, max_rooms: usize) {
@@ -91,9 +686,11 @@ impl Dungeon {
         let mut offset = Offset::default();
         let mut room_stack = RoomPath::new();
         let mut rooms: HashMap<Offset, Room> = HashMap::new();
+        let mut doors: HashMap<DoorId, Door> = HashMap::new();
 
         // Try to generate the requested number of rooms
         let mut index = 0;
+        let mut enclosed_count = 0;
         while index < max_rooms {
 
             // Drop a random number of rooms from the stack and continue
@@ -137,6 +734,26 @@ impl Dungeon {
                     // No free adjacent space was found, break out and continue
                     // from a previous room position in the stack
                     if next_dir == Side::None {
+
+                        // Unless all rooms which can still be dropped back to
+                        // are enclosed as well, then we would never finish and
+                        // continue from the first room with some space left,
+                        // checking this is costly so only do it once we got
+                        // stuck for a while
+                        let reachable = room_stack.len() - room_stack.len() / 2;
+                        enclosed_count += 1;
+                        if enclosed_count > 100 && room_stack[reachable..].iter().all(|o| Dungeon::is_enclosed(&rooms, *o)) {
+
+                            let open = sorted_offsets(rooms.keys().cloned().filter(|o| {
+                                Dungeon::is_enclosed(&rooms, *o) == false
+                            }));
+
+                            offset = open[0];
+                            room_stack.push(offset);
+                            continue;
+
+                        }
+
                         break;
 
                     // Found a free direction, continue there
@@ -147,9 +764,9 @@ impl Dungeon {
                 }
 
                 // Add a small chance of choosing a different position for
-                // continuing the room generation
+                // continuing the room generation, once there is one
                 let variance = rng.gen_range(0, 255);
-                if variance < 25 {
+                if variance < 25 && room_stack.len() > 0 {
                     break;
                 }
 
@@ -159,18 +776,25 @@ impl Dungeon {
 
                 // Create new room at current offset and connect it
                 // with the previous room
-                let mut room = Room::new(offset.x, offset.y);
+                let mut room = Room::new(self.ids.room(), offset.x, offset.y);
                 match room_stack.last() {
                     Some(offset) => {
                         let other = rooms.get_mut(&offset).unwrap();
-                        room.add_door_to(&other);
-                        other.add_door_to(&room);
+                        let id = self.ids.door();
+                        room.connect(id, &other);
+                        other.connect(id, &room);
+                        doors.insert(id, Door {
+                            id: id,
+                            rooms: (other.offset, room.offset),
+                            lock: DoorLock::None
+                        });
                     },
                     None => {}
                 }
 
                 rooms.insert(offset, room);
                 room_stack.push(offset);
+                enclosed_count = 0;
                 index += 1;
 
                 // Check if we either run out of hallway length
@@ -193,15 +817,7 @@ impl Dungeon {
         // TODO fail if we couldn't generate the desired number of rooms?
 
         // Set room connection types
-        for (_, room) in rooms.iter_mut() {
-            room.typ = match room.doors.len() {
-                1 => RoomType::End,
-                2 => RoomType::Hallway,
-                3 => RoomType::Intersection,
-                4 => RoomType::Crossing,
-                _ => RoomType::Invalid
-            };
-        }
+        Dungeon::set_room_types(&mut rooms);
 
         // Calculate bounds
         let mut min = Offset { x: 9999, y: 9999 };
@@ -220,28 +836,90 @@ impl Dungeon {
 
             // Translate offset and insert into dungeon room map
             room.offset = room.offset - min;
+            self.room_ids.insert(room.id, room.offset);
+            self.rooms.insert(room.offset, room);
+
+        }
+
+        // Translate the shared doors as well
+        for (id, mut door) in doors.into_iter() {
+            door.rooms = (door.rooms.0 - min, door.rooms.1 - min);
+            self.doors.insert(id, door);
+        }
+
+    }
+
+    fn is_enclosed(rooms: &HashMap<Offset, Room>, offset: Offset) -> bool {
+        Side::all().iter().all(|side| rooms.contains_key(&(offset + side.to_offset())))
+    }
+
+    fn create_layout_rooms(&mut self, layout: &Layout) {
+
+        for offset in layout.rooms.iter() {
+            let room = Room::new(self.ids.room(), offset.x, offset.y);
+            self.room_ids.insert(room.id, room.offset);
             self.rooms.insert(room.offset, room);
+        }
+
+        for &(a, b) in layout.doors.iter() {
+            self.connect_rooms(a, b, DoorLock::None);
+        }
+
+        Dungeon::set_room_types(&mut self.rooms);
 
+    }
+
+    fn set_room_types(rooms: &mut HashMap<Offset, Room>) {
+        for (_, room) in rooms.iter_mut() {
+            Dungeon::set_room_type(room);
         }
+    }
 
+    pub(crate) fn set_room_type(room: &mut Room) {
+        // The entrance, exit and secret rooms keep their type no matter how
+        // many doors they have
+        room.typ = match room.typ {
+            RoomType::Entrance | RoomType::Exit | RoomType::Secret => return,
+            _ => match room.doors.len() {
+                1 => RoomType::End,
+                2 => RoomType::Hallway,
+                3 => RoomType::Intersection,
+                4 => RoomType::Crossing,
+                _ => RoomType::Invalid
+            }
+        };
     }
 
     fn set_special_rooms(&mut self, rng: &mut StdRng) -> bool {
 
-        // Get the rooms most distant from any intersection
-        let mut ends = self.end_room_paths();
-        ends.sort_by(|a, b| {
-            b.len().cmp(&a.len())
-        });
+        // Get the rooms most distant from any intersection, we need at least
+        // 3 ends, one of which is long enough to hold both the exit and the
+        // boss room in front of it, otherwise we regrow branches until we do
+        let mut ends = Vec::new();
+        for branches in 0..4 {
+
+            ends = self.end_room_paths();
+            ends.sort_by(|a, b| {
+                b.len().cmp(&a.len())
+            });
+
+            if ends.len() >= 3 && ends[0..3].iter().any(|path| self.is_exit_path(path)) {
+                break;
+
+            } else if branches == 3 || self.grow_branch(rng) == false {
+                println!("Fatal: there must be at least 3 end paths in a dungeon");
+                return false;
+            }
 
-        // We need at least 3 ends, otherwise we have to retry
-        if ends.len() < 2 {
-            println!("Fatal: there must be at least 3 end paths in a dungeon");
-            return false;
         }
 
-        // Select the 3 longest ones and shuffle them
+        // Select the 3 longest ones and shuffle them, re-picking the exit
+        // if its path is too short
         rng.shuffle(&mut ends[0..3]);
+        if self.is_exit_path(&ends[1]) == false {
+            let other = if self.is_exit_path(&ends[0]) { 0 } else { 2 };
+            ends.swap(1, other);
+        }
 
         // Set Entrance
         {
@@ -252,110 +930,357 @@ impl Dungeon {
 
         // Set Exit room
         {
-            if ends[1].len() <= 1 {
-                println!("Fatal: there must be at least 2 rooms on the exit path, so we can place the boss room infront of it");
-                return false;
-            }
             let mut exit_room = self.rooms.get_mut(&ends[1][0]).unwrap();
             exit_room.typ = RoomType::Exit;
             self.exit_room = Some(exit_room.offset);
         }
 
         // Set Boss Room infront of exit
+        self.set_boss_room(ends[1][1]);
+
+        // Lock the room to the boss door with a big key
         {
-            // If the boos room has intersections we fail
-            let mut boss_room = self.rooms.get_mut(&ends[1][1]).unwrap();
-            if boss_room.doors.len() > 2 {
-                println!("Fatal: boss room may not be a intersection");
-                return false;
-            }
+            let door = self.door_between_mut(ends[1][2], ends[1][1]).unwrap();
+            door.lock = DoorLock::BossKey;
+        }
 
-            let exit_offset = self.exit_room.unwrap();
+        // Set Boss Key Room
+        {
+            let mut boss_key_room = self.rooms.get_mut(&ends[2][0]).unwrap();
+            boss_key_room.add_entity(self.ids.entity(), Entity::Chest(Chest {
+                item: Item::Key(Key::Boss)
+            }));
+        }
 
-            // Create Boss
-            boss_room.enemy = Some(Enemy {
-                typ: EnemyType::Boss,
-                triggers: vec![
-                    // Open door to exit once boss is defeated
-                    Trigger::OpenDoor(exit_offset)
-                ]
-            });
+        true
 
-            // Setup both doors
-            for door in boss_room.doors.iter_mut() {
+    }
 
-                // Lock door to exit
-                if door.to == exit_offset {
-                    door.lock = DoorLock::Trigger;
+    fn is_exit_path(&self, path: &RoomPath) -> bool {
+        // The boss room in front of the exit may not be an intersection
+        path.len() > 2 && self.rooms[&path[1]].doors.len() <= 2
+    }
 
-                // Lock entry door upon entrance
-                } else {
-                    door.lock = DoorLock::Trigger;
-                    door.triggers.push(Trigger::LockDoor(door.to));
-                }
+    fn grow_branch(&mut self, rng: &mut StdRng) -> bool {
 
+        // Grow a new branch of two rooms out of a hallway, turning it into
+        // an intersection
+        let mut hallways: Vec<(Offset, Offset, Option<Offset>)> = Vec::new();
+        for (offset, room) in self.rooms.iter() {
+            if room.doors.len() == 2 {
+                for side in Side::all() {
+                    let next = *offset + side.to_offset();
+                    if self.is_free_cell(next) {
+                        let end = Side::all().into_iter().map(|s| next + s.to_offset()).filter(|o| {
+                            *o != *offset && self.is_free_cell(*o)
+
+                        }).next();
+                        hallways.push((*offset, next, end));
+                    }
+                }
             }
+        }
 
-            self.boss_room = Some(boss_room.offset);
+        hallways.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
 
+        if hallways.len() == 0 {
+            return false;
         }
 
-        // Lock the room to the boss door with a big key
-        {
-            let mut before_boss_room = self.rooms.get_mut(&ends[1][2]).unwrap();
-            let door = before_boss_room.get_door_to_offset_mut(&ends[1][1]).unwrap();
-            door.lock = DoorLock::BossKey;
+        let (from, next, end) = hallways[rng.gen_range(0, hallways.len())];
+        self.add_connected_room(next, from);
+        if let Some(end) = end {
+            self.add_connected_room(end, next);
         }
 
-        // Set Boss Key Room
+        Dungeon::set_room_types(&mut self.rooms);
+        true
+
+    }
+
+    pub(crate) fn is_free_cell(&self, offset: Offset) -> bool {
+        offset.x >= 0 && offset.y >= 0 && self.rooms.contains_key(&offset) == false
+    }
+
+    pub(crate) fn add_connected_room(&mut self, offset: Offset, from: Offset) {
+        let room = Room::new(self.ids.room(), offset.x, offset.y);
+        self.room_ids.insert(room.id, room.offset);
+        self.rooms.insert(room.offset, room);
+        self.connect_rooms(from, offset, DoorLock::None);
+    }
+
+    pub(crate) fn connect_rooms(&mut self, a: Offset, b: Offset, lock: DoorLock) {
+
+        let id = self.ids.door();
+        let mut other = self.rooms.remove(&b).unwrap();
         {
-            let mut boss_key_room = self.rooms.get_mut(&ends[2][0]).unwrap();
-            boss_key_room.chest = Some(Chest {
-                item: Item::Key(Key::Boss)
-            });
+            let room = self.rooms.get_mut(&a).unwrap();
+            room.connect(id, &other);
+            other.connect(id, room);
         }
+        self.rooms.insert(b, other);
 
-        true
+        self.doors.insert(id, Door {
+            id: id,
+            rooms: (a, b),
+            lock: lock
+        });
 
     }
 
-    fn set_locked_doors(&mut self, rng: &mut StdRng) -> bool {
+    fn set_boss_room(&mut self, offset: Offset) {
 
-        // Get path from entrance to boss key
-        let mut boss_key_path = self.boss_key_path();
+        let exit_offset = self.exit_room.unwrap();
+        let boss_room = self.rooms.get_mut(&offset).unwrap();
+
+        // Create Boss
+        let exit_door = boss_room.connection_to(&exit_offset).unwrap().door;
+        boss_room.add_entity(self.ids.entity(), Entity::Enemy(Enemy {
+            typ: EnemyType::Boss,
+            weakness: Item::None,
+            triggers: vec![
+                // Open door to exit once boss is defeated
+                Trigger::OpenDoor(exit_door)
+            ]
+        }));
 
-        // Get path from entrance to boss door
-        let mut boss_door_path = self.boss_door_path();
-        boss_door_path.pop(); // Don't override the boss door
+        // Setup both doors
+        for connection in boss_room.doors.iter_mut() {
 
-        // Extract shared boss path
-        let mut shared_boss_path = RoomPath::new();
-        for (offset, _) in boss_key_path.iter().zip(
-            boss_door_path.iter()
+            // Lock door to exit
+            if connection.to == exit_offset {
+                self.doors.get_mut(&connection.door).unwrap().lock = DoorLock::Trigger;
 
-        ).filter(|&(a, b)| {
-            a == b
+            // Lock entry door upon entrance
+            } else {
+                let id = connection.door;
+                connection.triggers.push(Trigger::LockDoor(id));
+            }
 
-        }) {
-            shared_boss_path.push(*offset);
         }
 
-        // Drop shared path elements
-        for _ in 0..shared_boss_path.len() - 2 {
-            boss_key_path.remove(0);
-            boss_door_path.remove(0);
+        self.boss_room = Some(offset);
+
+    }
+
+    fn embed_mission(&mut self, rng: &mut StdRng, mission: &Mission) -> Result<(), MissionError> {
+
+        // Visit the mission graph depth first from the entrance so parents
+        // are always placed before their children and each branch is placed
+        // as a whole, dead ends then only backtrack within their own branch
+        let mut order: Vec<(usize, usize)> = Vec::new();
+        let mut to_visit: Vec<usize> = vec![0];
+        while let Some(node) = to_visit.pop() {
+            let children = mission.children(node);
+            order.push((node, children.len()));
+            to_visit.extend(children.into_iter().rev());
         }
 
-        // Get total number of locked doors to place
-        let empty_room_count = self.empty_rooms().len();
-        let door_count = empty_room_count / 4 + rng.gen_range(0, 1);
+        // Place every node next to its parent, backtracking on dead ends,
+        // with the number of children still to place next to each cell. Once
+        // a placement got stuck for a while we rather start over again, than
+        // to keep backtracking out of a corner it trapped itself in early on
+        let mut cells: HashMap<usize, Offset> = HashMap::new();
+        let mut placed = false;
+        for _ in 0..50 {
+
+            let mut used: HashMap<Offset, usize> = HashMap::new();
+            let mut budget = order.len() * 10;
+            cells.clear();
+            cells.insert(0, Offset::default());
+            used.insert(Offset::default(), order[0].1);
+
+            if Dungeon::place_mission(rng, mission, &order, 1, &mut cells, &mut used, &mut budget) {
+                placed = true;
+                break;
+            }
 
-        // Randomize paths
-        let mut paths = vec![
-            shared_boss_path.into_connected_path(),
-            boss_key_path.into_connected_path(),
-            boss_door_path.into_connected_path()
-        ];
+        }
+
+        if placed == false {
+            return Err(MissionError::NoLayout);
+        }
+
+        // Translate all cells so 0,0 is the top left border of the dungeon
+        let mut min = Offset { x: 9999, y: 9999 };
+        for (_, cell) in cells.iter() {
+            min.x = cmp::min(cell.x, min.x);
+            min.y = cmp::min(cell.y, min.y);
+        }
+
+        for &(node, _) in order.iter() {
+            let offset = *cells.get(&node).unwrap() - min;
+            let room = Room::new(self.ids.room(), offset.x, offset.y);
+            self.room_ids.insert(room.id, offset);
+            self.rooms.insert(offset, room);
+            cells.insert(node, offset);
+        }
+
+        // Connect the rooms and lock the doors leading into locked nodes
+        for &(node, _) in order[1..].iter() {
+
+            let to = *cells.get(&node).unwrap();
+            let from = *cells.get(&mission.parents[node].unwrap()).unwrap();
+            let lock = match mission.nodes[node] {
+                MissionNode::Lock => DoorLock::SmallKey,
+                MissionNode::Gate(ref item) => DoorLock::Item(item.clone()),
+                MissionNode::Boss => DoorLock::BossKey,
+                _ => DoorLock::None
+            };
+
+            self.connect_rooms(from, to, lock);
+
+        }
+
+        Dungeon::set_room_types(&mut self.rooms);
+
+        // Fill the rooms with their content
+        let mut boss_room = None;
+        for &(node, _) in order.iter() {
+
+            let offset = *cells.get(&node).unwrap();
+            let item = match mission.nodes[node] {
+                MissionNode::Entrance => {
+                    self.rooms.get_mut(&offset).unwrap().typ = RoomType::Entrance;
+                    self.entrance_room = Some(offset);
+                    None
+                },
+                MissionNode::Exit => {
+                    self.rooms.get_mut(&offset).unwrap().typ = RoomType::Exit;
+                    self.exit_room = Some(offset);
+                    None
+                },
+                MissionNode::Boss => {
+                    boss_room = Some(offset);
+                    None
+                },
+                MissionNode::Key => Some(Item::Key(Key::Small)),
+                MissionNode::BossKey => Some(Item::Key(Key::Boss)),
+                MissionNode::Item(ref item) => Some(item.clone()),
+                _ => None
+            };
+
+            if let Some(item) = item {
+                let room = self.rooms.get_mut(&offset).unwrap();
+                room.add_entity(self.ids.entity(), Entity::Chest(Chest {
+                    item: item
+                }));
+            }
+
+        }
+
+        match (boss_room, self.exit_room) {
+            (Some(offset), Some(_)) => {
+                self.set_boss_room(offset);
+                Ok(())
+            },
+            (None, _) => Err(MissionError::MissingBoss),
+            (_, None) => Err(MissionError::MissingExit)
+        }
+
+    }
+
+    fn place_mission(
+        rng: &mut StdRng,
+        mission: &Mission,
+        order: &[(usize, usize)],
+        index: usize,
+        cells: &mut HashMap<usize, Offset>,
+        used: &mut HashMap<Offset, usize>,
+        budget: &mut usize
+
+    ) -> bool {
+
+        if index == order.len() {
+            return true;
+
+        } else if *budget == 0 {
+            return false;
+        }
+
+        *budget -= 1;
+
+        let (node, children) = order[index];
+        let parent = *cells.get(&mission.parents[node].unwrap()).unwrap();
+        let free = |used: &HashMap<Offset, usize>, cell: Offset| {
+            Side::all().iter().filter(|side| {
+                used.contains_key(&(cell + side.to_offset())) == false
+
+            }).count()
+        };
+
+        // Only use cells with enough space left for the node's children,
+        // which don't take away the space needed by the children of other
+        // nodes either, and prefer the ones with the most space around them
+        // which lie further away from the entrance, so branches grow outwards
+        let mut candidates: Vec<(usize, usize, Offset)> = Side::all().iter().map(|side| {
+            parent + side.to_offset()
+
+        }).filter(|cell| {
+            used.contains_key(cell) == false
+                && free(used, *cell) >= children
+                && Side::all().iter().map(|side| *cell + side.to_offset()).all(|next| {
+                    next == parent || used.get(&next).map_or(true, |left| *left < free(used, next))
+                })
+
+        }).map(|cell| (free(used, cell), (cell.x.abs() + cell.y.abs()) as usize, cell)).collect();
+
+        rng.shuffle(&mut candidates);
+        candidates.sort_by(|a, b| (b.0, b.1).cmp(&(a.0, a.1)));
+
+        for (_, _, cell) in candidates {
+
+            cells.insert(node, cell);
+            used.insert(cell, children);
+            *used.get_mut(&parent).unwrap() -= 1;
+
+            if Dungeon::place_mission(rng, mission, order, index + 1, cells, used, budget) {
+                return true;
+            }
+
+            cells.remove(&node);
+            used.remove(&cell);
+            *used.get_mut(&parent).unwrap() += 1;
+
+        }
+
+        false
+
+    }
+
+    fn set_locked_doors(&mut self, rng: &mut StdRng) -> bool {
+
+        // Get the doors which every path to the boss key and boss room
+        // must pass through, ordered from the entrance onwards
+        let dominators = Graph::from_dungeon(self).dominators(self.entrance_room.unwrap());
+        let boss_key_doors = dominators.doors(*self.boss_key_path().last().unwrap());
+        let boss_room_doors = dominators.doors(self.boss_room.unwrap());
+
+        // Split them into the doors shared by both and the ones guarding only
+        // one of them, without overriding the boss door
+        let unlocked = |id: &DoorId| self.doors[id].lock == DoorLock::None;
+        let shared_doors: Vec<DoorId> = boss_key_doors.iter().filter(|id| {
+            boss_room_doors.contains(id) && unlocked(id)
+
+        }).cloned().collect();
+
+        let key_doors: Vec<DoorId> = boss_key_doors.iter().filter(|id| {
+            boss_room_doors.contains(id) == false && unlocked(id)
+
+        }).cloned().collect();
+
+        let boss_doors: Vec<DoorId> = boss_room_doors.iter().filter(|id| {
+            boss_key_doors.contains(id) == false && unlocked(id)
+
+        }).cloned().collect();
+
+        let mut paths = vec![shared_doors, key_doors, boss_doors];
+
+        // Get total number of locked doors to place, large dungeons have far
+        // more empty rooms than doors on the paths so cap it by those
+        let empty_room_count = self.empty_rooms().len();
+        let lockable_count = paths.iter().map(|p| cmp::max(p.len(), 1) - 1).sum();
+        let door_count = cmp::min(empty_room_count / 4, lockable_count) + rng.gen_range(0, 1);
 
         // Prioritize the longer paths for door placement
         paths.sort_by(|a, b| {
@@ -371,9 +1296,9 @@ impl Dungeon {
         while path_count > 0 && doors_locked < door_count {
 
             // Select the next available path
+            let index = path_index % path_count;
             let empty = {
 
-                let index = path_index % path_count;
                 let mut path = &mut paths[index];
 
                 // Get the all empty rooms from the current path
@@ -382,22 +1307,11 @@ impl Dungeon {
                     // If so, place a door on somewhere on the path
                     let door_index = rng.gen_range(0, 255) % path.len();
 
-                    // Place locked door between the selected room and the one
-                    // that comes after it on the path
-                    let room = self.rooms.get_mut(&path[door_index].0).unwrap();
-                    let door = room.get_door_to_offset_mut(&path[door_index].1).unwrap();
-
-                    // Do not use lock the same door twice
-                    if door.lock != DoorLock::None {
-                        return false;
-                    }
-
-                    // Set trigger and lock the door
-                    door.lock = DoorLock::SmallKey;
+                    // Lock the selected door and remove it from the path so
+                    // it is never locked twice
+                    self.doors.get_mut(&path[door_index]).unwrap().lock = DoorLock::SmallKey;
                     doors_on_path[index] += 1;
                     doors_locked += 1;
-
-                    // Remove the used room from the path
                     path.remove(door_index);
 
                     // Calculate the ratio of path len and door count
@@ -416,7 +1330,7 @@ impl Dungeon {
 
             // Remove paths once they're empty
             if empty {
-                paths.remove(path_index % path_count);
+                paths.remove(index);
                 path_count -= 1;
             }
 
@@ -435,115 +1349,97 @@ impl Dungeon {
     fn set_locked_keys(&mut self, rng: &mut StdRng) -> bool {
 
         // Keep track of the doors that we have unlocked
-        let mut unlocked_doors: HashMap<(Offset, Offset), bool> = HashMap::new();
-        let mut rooms_with_key: Vec<Offset> = Vec::new();
+        let mut unlocked_doors: HashSet<DoorId> = HashSet::new();
+        let mut rooms_with_key: HashSet<Offset> = HashSet::new();
 
-        loop {
+        // Warps only lead one way, so finding the rooms in front of a key
+        // requires knowing where they lead in from
+        let mut warped_from: HashMap<Offset, Vec<Offset>> = HashMap::new();
+        for (offset, room) in self.rooms.iter() {
+            for &(_, warp) in room.active_warps().iter() {
+                warped_from.entry(self.warp_target(warp)).or_insert_with(Vec::new).push(*offset);
+            }
+        }
+
+        // Empty rooms in front of the doors unlocked so far, end rooms are
+        // used first and rooms right next to other keys last
+        let mut ends = RoomPool::new();
+        let mut empty_rooms = RoomPool::new();
+        let mut close_to_keys = RoomPool::new();
 
-            // Now search through the dungeon startin from the entrance and find
-            // all reachable rooms before any locked doors
-            let rooms = self.connected_rooms(self.entrance_room.unwrap(), |room, door| {
+        let entrance = self.entrance_room.unwrap();
+        let mut reached: HashSet<Offset> = HashSet::new();
+        let mut to_visit: Vec<Offset> = vec![entrance];
+        reached.insert(entrance);
 
-                // Always stop at the boss door
-                if door.lock == DoorLock::BossKey {
-                    false
+        loop {
+
+            // Search through the dungeon starting from the rooms which just
+            // became reachable and find all rooms before any locked doors
+            let mut doors_unlocked = 0;
+            let mut behind_doors: Vec<Offset> = Vec::new();
+            while let Some(offset) = to_visit.pop() {
 
-                // See if the door is locked
-                } else if door.lock == DoorLock::SmallKey {
+                let room = self.rooms.get(&offset).unwrap();
+                if room.is_empty() {
+                    if room.typ == RoomType::End {
+                        ends.insert(offset);
 
-                    // See if we already unlocked it, if so we can continue
-                    // with the room behind it
-                    if unlocked_doors.contains_key(&(room.offset, door.to)) {
-                        true
+                    } else if self.is_next_to(offset, &rooms_with_key) {
+                        close_to_keys.insert(offset);
 
-                    // Otherwise we stop here
                     } else {
-                        false
+                        empty_rooms.insert(offset);
                     }
-
-                // For all open doors visit the room behind them
-                } else {
-                    true
                 }
 
-            });
-
-            // Go through all rooms and mark all doors from them as unlocked
-            let mut doors_unlocked = 0;
-            for offset in rooms.iter() {
-                let room = self.rooms.get(&offset).unwrap();
                 for d in room.doors.iter() {
-
-                    // Mark all doors with small keys as unlocked
-                    if d.lock == DoorLock::SmallKey {
-                        if unlocked_doors.contains_key(&(*offset, d.to)) == false {
-                            unlocked_doors.insert((*offset, d.to), true);
-                            doors_unlocked += 1;
+                    let door = self.doors.get(&d.door).unwrap();
+                    match door.lock {
+
+                        // Always stop at the boss door
+                        DoorLock::BossKey => {},
+
+                        // Mark all doors with small keys as unlocked and
+                        // continue behind them once their keys are placed
+                        DoorLock::SmallKey => {
+                            if unlocked_doors.insert(door.id) {
+                                doors_unlocked += 1;
+                                behind_doors.push(d.to);
+
+                            } else if reached.insert(d.to) {
+                                to_visit.push(d.to);
+                            }
+                        },
+
+                        // For all open doors visit the room behind them
+                        _ => {
+                            if door.is_passable_from(offset) && reached.insert(d.to) {
+                                to_visit.push(d.to);
+                            }
                         }
-                    }
 
+                    }
                 }
-            }
 
-            // Get all empty rooms from the set of rooms we found infront of
-            // the doors
-            let mut empty_rooms: Vec<_> = rooms.iter().cloned().filter(|offset| {
-                let room = self.rooms.get(offset).unwrap();
-                room.chest.is_none() && room.enemy.is_none() && room.switch.is_none()
+                for &(_, warp) in room.warps().iter() {
+                    let to = self.warp_target(warp);
+                    if warp.active && reached.insert(to) {
+                        to_visit.push(to);
+                    }
+                }
 
-            }).collect();
+            }
 
             // Check if we have enough empty rooms to place the required keys in
-            if empty_rooms.len() < doors_unlocked {
+            if ends.len() + empty_rooms.len() + close_to_keys.len() < doors_unlocked {
                 println!("Not enough empty rooms to place small keys in");
                 return false;
             }
 
-            // 1. Randomize rooms to use for key placement
-            rng.shuffle(&mut empty_rooms);
-
-            // 2. Calculate room distance to the next room which contains a key
-            let mut key_distances: HashMap<Offset, usize> = HashMap::new();
-            for offset in empty_rooms.iter() {
-
-                // Find the distances from the current empty room to all rooms
-                // which already contain keys
-                let mut min_key_distance = 9999;
-                for key in rooms_with_key.iter() {
-                    let path = self.find_room_path(*offset, |room, _| {
-                        room.offset == *key
-
-                    }).unwrap();
-                    min_key_distance = cmp::min(min_key_distance, path.len() - 1);
-                }
-
-                key_distances.insert(*offset, min_key_distance);
-
-            }
-
-            // 3. Split between end rooms (not entrance(!)) and all others
-            let (mut ends, empty_rooms): (Vec<_>, Vec<_>) = empty_rooms.into_iter().partition(|offset| {
-                self.rooms.get(offset).unwrap().typ == RoomType::End
-            });
-
-            // 4. Split out rooms which are close to other rooms with keys and
-            // move them to the back of the list
-            let (mut close_to_keys, mut empty_rooms): (Vec<_>, Vec<_>) = empty_rooms.into_iter().partition(|offset| {
-                *key_distances.get(&offset).unwrap() < 2
-            });
-
-            // 5. Merge them back together
-            empty_rooms.append(&mut close_to_keys);
-
-            // 6. Take the first end room (if any) and put it back
-            match ends.pop() {
-                Some(offset) => {
-                    empty_rooms.insert(0, offset);
-                },
-                None => {}
-            }
-
-            // 7. Now place the keys in the first rooms from the list
+            // Now place the keys in an end room (if any) and otherwise in random
+            // rooms, preferring the ones which are not close to other keys
+            let mut key_rooms: Vec<Offset> = Vec::new();
             let mut key_triggers = vec![
                 // TODO use enum
                 0,
@@ -553,42 +1449,159 @@ impl Dungeon {
                 2,
                 2,
                 2,
-                2
+                2,
+                3,
+                3,
+                4,
+                4
             ];
 
             for i in 0..doors_unlocked {
-                let mut room = self.rooms.get_mut(empty_rooms.get(i).unwrap()).unwrap();
+
+                let offset = if i == 0 && ends.len() > 0 {
+                    ends.pick(rng)
+
+                } else if empty_rooms.len() > 0 {
+                    empty_rooms.pick(rng)
+
+                } else if close_to_keys.len() > 0 {
+                    close_to_keys.pick(rng)
+
+                } else {
+                    ends.pick(rng)
+
+                }.unwrap();
+
+                let mut room = self.rooms.get_mut(&offset).unwrap();
 
                 // Select a random trigger
                 rng.shuffle(&mut key_triggers);
 
-                // TODO create trigger sets
+                let room_id = room.id;
                 match key_triggers[0] {
                     0 => {
-                        room.chest = Some(Chest {
+                        room.add_entity(self.ids.entity(), Entity::Chest(Chest {
                             item: Item::Key(Key::Small)
-                        })
+                        }));
                     },
                     1 => {
-                        room.switch = Some(Switch {
-                            // TODO chance of either dropping key or spawning chest with key
-                            triggers: vec![Trigger::Item(Item::Key(Key::Small))]
-                        })
+                        room.add_entity(self.ids.entity(), Entity::Switch(Switch {
+                            triggers: vec![Dungeon::create_key_trigger(rng)]
+                        }));
                     },
                     2 => {
                         // TODO random chance for locking the door behind on entry and unlocking
                         // once enemies are defeated
-                        room.enemy = Some(Enemy {
-                            typ: EnemyType::Small,
-                            // TODO random chance for big enemies which stay defeated?
-                            // TODO chance of either dropping key or spawning chest with key
-                            triggers: vec![Trigger::Item(Item::Key(Key::Small))]
-                        })
+                        // TODO random chance for big enemies which stay defeated?
+                        let count = rng.gen_range(1, 4);
+                        if count == 1 {
+                            room.add_entity(self.ids.entity(), Entity::Enemy(Enemy {
+                                typ: EnemyType::Small,
+                                weakness: Item::None,
+                                triggers: vec![Dungeon::create_key_trigger(rng)]
+                            }));
+
+                        // Drop the key once all enemies are defeated
+                        } else {
+                            for _ in 0..count {
+                                room.add_entity(self.ids.entity(), Entity::Enemy(Dungeon::create_small_enemy()));
+                            }
+                            room.trigger_sets.push(TriggerSet {
+                                condition: Condition::EnemiesDefeated(room_id),
+                                triggers: vec![Dungeon::create_key_trigger(rng)]
+                            });
+                        }
+                    },
+                    3 => {
+                        // Hide the key behind a compound condition made up
+                        // of the room's entities
+                        let condition = match rng.gen_range(0, 4) {
+                            0 => {
+                                let switch = self.ids.entity();
+                                room.add_entity(self.ids.entity(), Entity::Enemy(Dungeon::create_small_enemy()));
+                                room.add_entity(switch, Entity::Switch(Switch {
+                                    triggers: Vec::new()
+                                }));
+                                Condition::All(vec![
+                                    Condition::EnemiesDefeated(room_id),
+                                    Condition::SwitchPressed(switch)
+                                ])
+                            },
+                            1 => {
+                                for torch in Dungeon::create_torches(rng) {
+                                    room.add_entity(self.ids.entity(), Entity::Torch(torch));
+                                }
+                                Condition::TorchesLit(room_id)
+                            },
+                            2 => {
+                                room.add_entity(self.ids.entity(), Entity::Enemy(Dungeon::create_small_enemy()));
+                                for torch in Dungeon::create_torches(rng) {
+                                    room.add_entity(self.ids.entity(), Entity::Torch(torch));
+                                }
+                                Condition::Any(vec![
+                                    Condition::EnemiesDefeated(room_id),
+                                    Condition::TorchesLit(room_id)
+                                ])
+                            },
+                            _ => {
+                                let switches = vec![self.ids.entity(), self.ids.entity()];
+                                for id in switches.iter() {
+                                    room.add_entity(*id, Entity::Switch(Switch {
+                                        triggers: Vec::new()
+                                    }));
+                                }
+                                Condition::Any(switches.into_iter().map(|id| {
+                                    Condition::SwitchPressed(id)
+
+                                }).collect())
+                            }
+                        };
+
+                        room.trigger_sets.push(TriggerSet {
+                            condition: condition,
+                            triggers: vec![Dungeon::create_key_trigger(rng)]
+                        });
+                    },
+                    4 => {
+                        // Hide the key in one of several pots
+                        let count = rng.gen_range(2, 5);
+                        let key_pot = rng.gen_range(0, count);
+                        for i in 0..count {
+                            room.add_entity(self.ids.entity(), Entity::Pot(Pot {
+                                item: if i == key_pot {
+                                    Item::Key(Key::Small)
+
+                                } else {
+                                    Item::None
+                                }
+                            }));
+                        }
                     },
                     _ => unreachable!()
                 }
 
-                rooms_with_key.push(room.offset);
+                key_rooms.push(offset);
+
+            }
+
+            // Rooms from which the new keys are one step away are now close
+            // to keys as well
+            for offset in key_rooms.into_iter() {
+
+                let room = self.rooms.get(&offset).unwrap();
+                let doors = room.doors.iter().filter(|d| {
+                    self.doors.get(&d.door).unwrap().is_passable_from(d.to)
+
+                }).map(|d| d.to);
+
+                let warps = warped_from.get(&offset).into_iter().flat_map(|rooms| rooms.iter().cloned());
+                for from in doors.chain(warps) {
+                    if empty_rooms.remove(from) {
+                        close_to_keys.insert(from);
+                    }
+                }
+
+                rooms_with_key.insert(offset);
 
             }
 
@@ -597,6 +1610,13 @@ impl Dungeon {
                 break;
             }
 
+            // Otherwise continue with the rooms behind them
+            for offset in behind_doors.into_iter() {
+                if reached.insert(offset) {
+                    to_visit.push(offset);
+                }
+            }
+
         }
 
         true
@@ -604,135 +1624,1412 @@ impl Dungeon {
     }
 
 
-    // Room collection methods ------------------------------------------------
+    fn set_item_gates(&mut self, rng: &mut StdRng) -> bool {
 
-    fn connected_rooms<F>(
-        &self, start: Offset, callback: F
+        // Figure out the order in which rooms become reachable when walking
+        // through the dungeon
+        let (valid, steps) = self.walk_through();
+        if valid == false {
+            println!("Failed to walk through dungeon before placing item gates");
+            return false;
+        }
 
-    ) -> Vec<Offset> where F : Fn(&Room, &Door) -> bool {
+        let mut items = vec![Item::Hookshot, Item::Bow, Item::Bombs];
+        rng.shuffle(&mut items);
+        let item = items.remove(0);
 
-        let mut visited: HashMap<Offset, bool> = HashMap::new();
-        let mut to_visit: Vec<Offset> = vec![start];
+        // Either hand out the item as the reward for defeating a miniboss
+        let entrance = self.entrance_room.unwrap();
+        let miniboss_reward = if rng.gen_range(0, 2) == 0 {
+            self.set_miniboss_room(rng, &item, &steps)
 
-        let mut rooms = Vec::new();
-        while to_visit.len() > 0 {
+        } else {
+            None
+        };
 
-            // Get next room to visit
-            let offset = to_visit.remove(0);
-            let room = self.rooms.get(&offset).unwrap();
+        let chest_offset = match miniboss_reward {
+            Some(offset) => offset,
+            None => {
 
-            // Add current room to visited list
-            visited.insert(offset, true);
-            rooms.push(offset);
+                // Or sort all empty rooms by the time at which they can be
+                // reached
+                let distances = self.room_distances(&[entrance]);
+                let mut candidates: Vec<(usize, usize, Offset)> = self.empty_rooms().into_iter().filter(|offset| {
+                    *offset != entrance
 
-            // Add all connected rooms to the to_visit list
-            for d in room.doors.iter() {
-                if visited.contains_key(&d.to) == false  {
+                }).map(|offset| {
+                    (*steps.get(&offset).unwrap(), *distances.get(&offset).unwrap(), offset)
 
-                    // Invoke callback and add the room behind the door
-                    if callback(&room, &d) == true {
-                        to_visit.push(d.to);
-                        visited.insert(d.to, true);
-                    }
+                }).collect();
 
+                if candidates.len() == 0 {
+                    println!("No empty rooms left to place the dungeon item in");
+                    return false;
                 }
-            }
 
-        }
+                candidates.sort();
 
-        rooms
+                // And place the big chest roughly midway through the dungeon
+                let middle = candidates.len() / 2;
+                let index = middle - rng.gen_range(0, middle / 2 + 1);
+                let offset = candidates[index].2;
 
-    }
+                self.rooms.get_mut(&offset).unwrap().add_entity(self.ids.entity(), Entity::Chest(Chest {
+                    item: item.clone()
+                }));
 
-    fn empty_rooms(&self) -> Vec<Offset> {
+                offset
 
-        let mut empty_rooms: Vec<Offset> = Vec::new();
+            }
+        };
 
-        for (offset, room) in self.rooms.iter() {
-            if room.typ == RoomType::Exit {
-                continue;
+        let chest_step = *steps.get(&chest_offset).unwrap();
 
-            } else if let Some(_) = room.enemy {
-                continue;
+        // Never gate any of the rooms which lead up to the chest
+        let tree = self.room_tree(entrance);
+        let chest_path: HashSet<Offset> = tree.path(chest_offset).iter().cloned().collect();
 
-            } else if let Some(_) = room.chest {
-                continue;
+        // Collect all unlocked doors which are only walked through after the
+        // chest can be reached
+        let mut doors: Vec<(Offset, Offset)> = Vec::new();
+        for (offset, room) in self.rooms.iter() {
 
-            } else if let Some(_) = room.switch {
+            if chest_path.contains(offset) || Some(*offset) == self.boss_room {
                 continue;
+            }
 
-            } else {
-                empty_rooms.push(*offset);
+            let step = *steps.get(offset).unwrap();
+            for d in room.doors.iter() {
+                if self.doors.get(&d.door).unwrap().lock == DoorLock::None
+                    && chest_path.contains(&d.to) == false
+                    && Some(d.to) != self.boss_room
+                    && Some(d.to) != self.exit_room
+                    && *steps.get(&d.to).unwrap() >= cmp::max(step, chest_step)
+                    && tree.is_on_path(*offset, d.to) {
+
+                    doors.push((*offset, d.to));
+                }
             }
+
         }
 
-        // HashMap iteration order is random, but we need the rooms to be
-        // deterministic
-        empty_rooms.sort_by(|a, b| {
-            (a.x + a.y * 1000).cmp(&(b.x + b.y * 1000))
-        });
+        doors.sort();
 
-        empty_rooms
+        // Gate a few of the doors with the dungeon item
+        rng.shuffle(&mut doors);
+        let gate_count = cmp::min(doors.len(), rng.gen_range(1, 4));
+        for &(from, to) in doors[0..gate_count].iter() {
+            let door = self.door_between_mut(from, to).unwrap();
+            door.lock = DoorLock::Item(item.clone());
+        }
+
+        // Optionally require the item to defeat the boss
+        if rng.gen_range(0, 2) == 0 {
+            let boss_room = self.rooms.get_mut(&self.boss_room.unwrap()).unwrap();
+            for &mut (_, ref mut entity) in boss_room.entities.iter_mut() {
+                if let Entity::Enemy(ref mut enemy) = *entity {
+                    if enemy.typ == EnemyType::Boss {
+                        enemy.weakness = item.clone();
+                    }
+                }
+            }
+        }
+
+        true
+
+    }
+
+    fn set_miniboss_room(
+        &mut self, rng: &mut StdRng, item: &Item, steps: &HashMap<Offset, usize>
+
+    ) -> Option<Offset> {
+
+        // Don't place a miniboss in very short dungeons
+        let critical_path = self.boss_door_path();
+        if critical_path.len() < 5 {
+            return None;
+        }
+
+        // Find the empty room closest to the middle of the critical path,
+        // excluding the entrance and the rooms infront of the boss, which can
+        // be reached before the boss key
+        let boss_key_step = *steps.get(self.boss_key_path().last().unwrap()).unwrap();
+        let empty_rooms: HashSet<Offset> = self.empty_rooms().into_iter().collect();
+        let middle = critical_path.len() / 2;
+        let mut candidates: Vec<(usize, Offset)> = critical_path[1..critical_path.len() - 2].iter().enumerate().filter(|&(_, offset)| {
+            empty_rooms.contains(offset) && *steps.get(offset).unwrap() <= boss_key_step
+
+        }).map(|(index, offset)| {
+            ((index as i32 + 1 - middle as i32).abs() as usize, *offset)
+
+        }).collect();
+
+        candidates.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+        });
+
+        let offset = match candidates.first() {
+            Some(&(_, offset)) => offset,
+            None => return None
+        };
+
+        // Look for an adjacent dead end which can serve as a reward room
+        let reward_room = self.rooms.get(&offset).unwrap().doors.iter().map(|d| {
+            d.to
+
+        }).filter(|to| {
+            critical_path.contains(to) == false
+                && empty_rooms.contains(to)
+                && self.rooms.get(to).unwrap().typ == RoomType::End
+
+        }).next();
+
+        let reward_room = if rng.gen_range(0, 2) == 0 {
+            reward_room
+
+        } else {
+            None
+        };
+
+        {
+            let room = self.rooms.get_mut(&offset).unwrap();
+            let doors: Vec<DoorId> = room.doors.iter().map(|d| d.door).collect();
+
+            // Lock all doors upon entrance
+            for connection in room.doors.iter_mut() {
+                for id in doors.iter() {
+                    connection.triggers.push(Trigger::LockDoor(*id));
+                }
+            }
+
+            // Open them again once the miniboss is defeated
+            let mut triggers: Vec<Trigger> = doors.iter().map(|id| {
+                Trigger::OpenDoor(*id)
+
+            }).collect();
+
+            // Either open up the reward room or spawn the chest right away
+            match reward_room {
+                Some(to) => {
+                    let id = room.connection_to(&to).unwrap().door;
+                    self.doors.get_mut(&id).unwrap().lock = DoorLock::Trigger;
+                },
+                None => {
+                    triggers.push(Trigger::Chest(Chest {
+                        item: item.clone()
+                    }));
+                }
+            }
+
+            room.add_entity(self.ids.entity(), Entity::Enemy(Enemy {
+                typ: EnemyType::Big,
+                weakness: Item::None,
+                triggers: triggers
+            }));
+
+        }
+
+        self.miniboss_room = Some(offset);
+
+        match reward_room {
+            Some(to) => {
+                self.rooms.get_mut(&to).unwrap().add_entity(self.ids.entity(), Entity::Chest(Chest {
+                    item: item.clone()
+                }));
+                Some(to)
+            },
+            None => Some(offset)
+        }
+
+    }
+
+    fn set_warps(&mut self, rng: &mut StdRng) -> bool {
+
+        // Open up a warp from the boss key room back to the entrance once the
+        // key has been collected, if the way back would otherwise be long
+        let boss_key_path = self.boss_key_path();
+        if boss_key_path.len() > 4 && rng.gen_range(0, 2) == 0 {
+
+            let entrance = self.rooms.get(&self.entrance_room.unwrap()).unwrap().id;
+            let offset = *boss_key_path.last().unwrap();
+            let room = self.rooms.get_mut(&offset).unwrap();
+
+            let chest = room.chests().iter().filter(|&&(_, chest)| {
+                chest.item == Item::Key(Key::Boss)
+
+            }).map(|&(id, _)| id).next().unwrap();
+
+            let warp = self.ids.entity();
+            room.add_entity(warp, Entity::Warp(Warp {
+                to: entrance,
+                active: false
+            }));
+
+            room.trigger_sets.push(TriggerSet {
+                condition: Condition::ChestOpened(chest),
+                triggers: vec![Trigger::Warp(warp)]
+            });
+
+        }
+
+        true
+
+    }
+
+    fn set_barriers(&mut self, rng: &mut StdRng) -> bool {
+
+        if rng.gen_range(0, 2) == 0 {
+            return true;
+        }
+
+        let barrier_count = rng.gen_range(1, 3);
+        for (from, to) in self.state_gate_doors(rng, barrier_count).into_iter() {
+
+            let color = if rng.gen_range(0, 2) == 0 {
+                Color::Red
+
+            } else {
+                Color::Blue
+            };
+
+            self.door_between_mut(from, to).unwrap().lock = DoorLock::Barrier(color);
+
+            let room = self.rooms.get_mut(&from).unwrap();
+            if room.crystals().len() == 0 {
+                room.add_entity(self.ids.entity(), Entity::Crystal(Crystal));
+            }
+
+        }
+
+        true
+
+    }
+
+    fn set_water_levels(&mut self, rng: &mut StdRng) -> bool {
+
+        if rng.gen_range(0, 2) == 0 {
+            return true;
+        }
+
+        // The water starts out drained, so the first door is flooded and a
+        // second one drained again, otherwise its switch would do nothing,
+        // each door has a switch in front of it which sets the level it needs
+        let water_count = rng.gen_range(1, 3);
+        for (index, (from, to)) in self.state_gate_doors(rng, water_count).into_iter().enumerate() {
+
+            let level = if index == 0 { 1 } else { 0 };
+            self.door_between_mut(from, to).unwrap().lock = DoorLock::Water(level);
+
+            let room = self.rooms.get_mut(&from).unwrap();
+            room.add_entity(self.ids.entity(), Entity::Switch(Switch {
+                triggers: vec![Trigger::SetWaterLevel(level)]
+            }));
+
+        }
+
+        true
+
+    }
+
+    fn state_gate_doors(&self, rng: &mut StdRng, count: usize) -> Vec<(Offset, Offset)> {
+
+        // Collect all unlocked doors outside of the boss area
+        let entrance = self.entrance_room.unwrap();
+        let tree = self.room_tree(entrance);
+        let mut doors: Vec<(Offset, Offset)> = Vec::new();
+        for (offset, room) in self.rooms.iter() {
+
+            if self.is_special_room(*offset) {
+                continue;
+            }
+
+            for d in room.doors.iter() {
+                if self.doors.get(&d.door).unwrap().lock == DoorLock::None
+                    && self.is_special_room(d.to) == false
+                    && tree.is_on_path(*offset, d.to) {
+
+                    doors.push((*offset, d.to));
+                }
+            }
+
+        }
+
+        doors.sort();
+        rng.shuffle(&mut doors);
+
+        // Doors whose state is already depending on the environment
+        let mut gates: Vec<(Offset, Offset)> = self.doors.values().filter(|d| {
+            d.lock.is_state_dependent()
+
+        }).map(|d| d.rooms).collect();
+
+        let mut chosen = Vec::new();
+        for &(from, to) in doors.iter() {
+
+            if chosen.len() == count {
+                break;
+            }
+
+            // The switch in front of each gate must be reachable in every
+            // state, so no gate may lie on the way to another one
+            let path = tree.path(from);
+            let blocked = path.windows(2).any(|w| {
+                gates.contains(&(w[0], w[1])) || gates.contains(&(w[1], w[0]))
+            });
+
+            let blocking = gates.iter().any(|&(a, b)| {
+                let gate = if tree.order.get(&a) < tree.order.get(&b) { a } else { b };
+                tree.is_on_path(to, gate)
+            });
+
+            if blocked || blocking {
+                continue;
+            }
+
+            gates.push((from, to));
+            chosen.push((from, to));
+
+        }
+
+        chosen
+
+    }
+
+    fn set_dark_rooms(&mut self, rng: &mut StdRng) -> bool {
+
+        if rng.gen_range(0, 2) == 0 {
+            return true;
+        }
+
+        let (valid, steps) = self.walk_through();
+        if valid == false {
+            println!("Failed to walk through dungeon before placing dark rooms");
+            return false;
+        }
+
+        // Place the lantern into one of the earlier reachable empty rooms,
+        // the walk through stops at the exit so not all rooms have a step
+        let entrance = self.entrance_room.unwrap();
+        let mut candidates: Vec<(usize, Offset)> = self.empty_rooms().into_iter().filter(|offset| {
+            *offset != entrance
+                && self.is_special_room(*offset) == false
+                && steps.contains_key(offset)
+
+        }).map(|offset| {
+            (*steps.get(&offset).unwrap(), offset)
+
+        }).collect();
+
+        if candidates.len() == 0 {
+            return true;
+        }
+
+        candidates.sort();
+
+        let (lantern_step, lantern_offset) = candidates[rng.gen_range(0, candidates.len() / 2 + 1)];
+        self.rooms.get_mut(&lantern_offset).unwrap().add_entity(self.ids.entity(), Entity::Chest(Chest {
+            item: Item::Lantern
+        }));
+
+        // Only darken rooms which are reached after the lantern
+        let mut rooms = sorted_offsets(self.rooms.keys().cloned().filter(|offset| {
+            *offset != lantern_offset
+                && *offset != entrance
+                && self.is_special_room(*offset) == false
+                && steps.get(offset).map_or(false, |step| *step >= lantern_step)
+        }));
+        rng.shuffle(&mut rooms);
+
+        let dark_count = cmp::min(rooms.len(), rng.gen_range(1, 4));
+        for offset in rooms[0..dark_count].iter() {
+            self.rooms.get_mut(offset).unwrap().dark = true;
+        }
+
+        true
+
+    }
+
+    fn set_shortcuts(&mut self, rng: &mut StdRng) -> bool {
+
+        let steps = self.walk_through().1;
+        let entrance = self.entrance_room.unwrap();
+
+        // Add a few one-way doors between neighbouring rooms which are not
+        // yet connected, leading from deep rooms back towards the entrance
+        let shortcut_count = rng.gen_range(0, 3);
+        for _ in 0..shortcut_count {
+
+            let depths = self.room_distances(&[entrance]);
+            let mut shortcuts: Vec<(usize, Offset, Offset)> = Vec::new();
+            for (offset, room) in self.rooms.iter() {
+
+                if self.is_special_room(*offset) {
+                    continue;
+                }
+
+                for side in Side::all() {
+
+                    let to = *offset + side.to_offset();
+                    if self.rooms.contains_key(&to) == false
+                        || room.connection_to(&to).is_some()
+                        || self.is_special_room(to) {
+                        continue;
+                    }
+
+                    // The shortcut may only lead to rooms which are reachable
+                    // before the deeper one so it cannot skip any locks
+                    match (steps.get(offset), steps.get(&to)) {
+                        (Some(from_step), Some(to_step)) if to_step <= from_step => {},
+                        _ => continue
+                    }
+
+                    // Only worth it if the way back is long, which it is at
+                    // least as the difference in depth between the rooms
+                    let (from_depth, to_depth) = (*depths.get(offset).unwrap(), *depths.get(&to).unwrap());
+                    if to_depth + 3 < from_depth {
+                        shortcuts.push((from_depth - to_depth, *offset, to));
+                    }
+
+                }
+
+            }
+
+            // Prefer the shortcuts which save the most backtracking
+            shortcuts.sort_by(|a, b| {
+                (b.0, a.1, a.2).cmp(&(a.0, b.1, b.2))
+            });
+
+            match shortcuts.first() {
+                Some(&(_, from, to)) => {
+                    self.connect_rooms(from, to, DoorLock::OneWay(from));
+                    Dungeon::set_room_type(self.rooms.get_mut(&from).unwrap());
+                    Dungeon::set_room_type(self.rooms.get_mut(&to).unwrap());
+                },
+                None => break
+            }
+
+        }
+
+        true
+
+    }
+
+    fn set_secret_rooms(&mut self, rng: &mut StdRng) -> bool {
+
+        // Calculate bounds so secret rooms stay within the dungeon's grid
+        let mut max = Offset { x: 0, y: 0 };
+        for (offset, _) in self.rooms.iter() {
+            max.x = cmp::max(offset.x, max.x);
+            max.y = cmp::max(offset.y, max.y);
+        }
+
+        // Find all free cells next to normal rooms
+        let mut cells: Vec<(Offset, Offset)> = Vec::new();
+        for (offset, _) in self.rooms.iter() {
+
+            if self.is_special_room(*offset) || Some(*offset) == self.entrance_room {
+                continue;
+            }
+
+            for side in Side::all() {
+                let to = *offset + side.to_offset();
+                if to.x >= 0 && to.y >= 0 && to.x <= max.x && to.y <= max.y
+                    && self.rooms.contains_key(&to) == false {
+                    cells.push((*offset, to));
+                }
+            }
+
+        }
+
+        cells.sort_by(|a, b| (a.1, a.0).cmp(&(b.1, b.0)));
+        cells.dedup_by(|a, b| a.1 == b.1);
+        rng.shuffle(&mut cells);
+
+        let secret_count = cmp::min(cells.len(), rng.gen_range(0, 3));
+        for &(from, to) in cells[0..secret_count].iter() {
+
+            // Reward the player with either an extra key or some currency
+            let item = if rng.gen_range(0, 3) == 0 {
+                Item::Key(Key::Small)
+
+            } else {
+                Item::Currency(rng.gen_range(1, 5) * 50)
+            };
+
+            let mut secret = Room::new(self.ids.room(), to.x, to.y);
+            secret.typ = RoomType::Secret;
+            secret.add_entity(self.ids.entity(), Entity::Chest(Chest {
+                item: item
+            }));
+
+            self.room_ids.insert(secret.id, to);
+            self.rooms.insert(to, secret);
+            self.connect_rooms(from, to, DoorLock::Hidden);
+            Dungeon::set_room_type(self.rooms.get_mut(&from).unwrap());
+
+        }
+
+        true
+
+    }
+
+    fn set_traps(&mut self, rng: &mut StdRng) -> bool {
+
+        let (valid, steps) = self.walk_through();
+        if valid == false {
+            println!("Failed to walk through dungeon before placing traps");
+            return false;
+        }
+
+        // The room infront of the boss door should stay free of traps
+        let antechamber = self.doors.values().filter(|d| d.lock == DoorLock::BossKey).map(|d| {
+            d.other(self.boss_room.unwrap())
+
+        }).next();
+
+        let entrance = self.entrance_room.unwrap();
+        let max_step = steps.values().cloned().max().unwrap_or(0);
+        let mut rooms: Vec<(usize, Offset)> = steps.iter().filter(|&(offset, _)| {
+            *offset != entrance
+                && Some(*offset) != antechamber
+                && self.is_special_room(*offset) == false
+
+        }).map(|(offset, step)| (*step, *offset)).collect();
+
+        rooms.sort();
+
+        // Easy dungeons place traps as if rooms were only half as deep and
+        // never use blades, hard ones as if they were half again as deep
+        let (depth, level) = match self.difficulty {
+            Difficulty::Easy => (1, 0),
+            Difficulty::Normal => (2, 1),
+            Difficulty::Hard => (3, 2)
+        };
+
+        // Rooms deeper into the dungeon are more likely to contain traps and
+        // their traps hurt more
+        for &(step, offset) in rooms.iter() {
+
+            if rng.gen_range(0, max_step + 2) > step * depth / 2 {
+                continue;
+            }
+
+            let difficulty = cmp::max(level + step * 3 / (max_step + 1), 1);
+            let count = rng.gen_range(1, difficulty + 1);
+            let room = self.rooms.get_mut(&offset).unwrap();
+            for _ in 0..count {
+
+                let typ = match rng.gen_range(0, 3) {
+                    0 => TrapType::Spikes,
+                    1 => TrapType::FallingFloor,
+                    _ if level == 0 => TrapType::Spikes,
+                    _ => TrapType::Blade
+                };
+
+                room.add_entity(self.ids.entity(), Entity::Trap(Trap {
+                    typ: typ,
+                    damage: difficulty,
+                    speed: if typ == TrapType::Blade {
+                        rng.gen_range(1, difficulty + 2)
+
+                    } else {
+                        0
+                    }
+                }));
+
+            }
+
+        }
+
+        true
+
+    }
+
+    fn create_key_trigger(rng: &mut StdRng) -> Trigger {
+
+        // Either drop the key directly or spawn a chest which contains it
+        if rng.gen_range(0, 3) == 0 {
+            Trigger::Chest(Chest {
+                item: Item::Key(Key::Small)
+            })
+
+        } else {
+            Trigger::Item(Item::Key(Key::Small))
+        }
+
+    }
+
+    fn create_small_enemy() -> Enemy {
+        Enemy {
+            typ: EnemyType::Small,
+            weakness: Item::None,
+            triggers: Vec::new()
+        }
+    }
+
+    fn create_torches(rng: &mut StdRng) -> Vec<Torch> {
+
+        // Always leave at least one torch unlit so the condition
+        // requires some work
+        let count = rng.gen_range(2, 5);
+        let mut torches = vec![Torch {
+            lit: false
+        }];
+
+        for _ in 1..count {
+            torches.push(Torch {
+                lit: rng.gen_range(0, 3) == 0
+            });
+        }
+
+        torches
+
+    }
+
+
+    // Mutation Helpers -------------------------------------------------------
+
+    // Rooms which mutations have to leave in place
+    pub(crate) fn is_fixed_room(&self, offset: Offset) -> bool {
+        Some(offset) == self.entrance_room
+            || self.is_special_room(offset)
+            || self.rooms.get(&offset).unwrap().typ == RoomType::Secret
+    }
+
+    pub(crate) fn remove_door(&mut self, id: DoorId) {
+        let door = self.doors.remove(&id).unwrap();
+        for offset in [door.rooms.0, door.rooms.1].iter() {
+            if let Some(room) = self.rooms.get_mut(offset) {
+                room.doors.retain(|d| d.door != id);
+                Dungeon::set_room_type(room);
+            }
+        }
+    }
+
+    pub(crate) fn remove_room(&mut self, offset: Offset) {
+        let doors: Vec<DoorId> = self.rooms.get(&offset).unwrap().doors.iter().map(|d| d.door).collect();
+        for id in doors {
+            self.remove_door(id);
+        }
+        let room = self.rooms.remove(&offset).unwrap();
+        self.room_ids.remove(&room.id);
+    }
+
+
+    // Validation Methods -----------------------------------------------------
+
+    pub fn validate(&self) -> bool {
+
+        let (valid, steps) = self.walk_through();
+
+        // The miniboss must be reachable before the boss key
+        match self.miniboss_room {
+            Some(offset) => {
+                let boss_key_room = self.rooms.values().find(|room| {
+                    room.chests().iter().any(|&(_, chest)| chest.item == Item::Key(Key::Boss))
+
+                }).map(|room| room.offset);
+
+                match (steps.get(&offset), boss_key_room.and_then(|room| steps.get(&room))) {
+                    (Some(miniboss_step), Some(boss_key_step)) => {
+                        valid && miniboss_step <= boss_key_step
+                    },
+                    _ => false
+                }
+            },
+            None => valid
+        }
+
+    }
+
+    pub fn metrics(&self) -> Metrics {
+
+        let graph = Graph::from_dungeon(self);
+        let entrance = self.entrance_room.unwrap();
+        let exit = self.exit_room.unwrap();
+
+        // Follow the walk through from event to event, pairing up every key
+        // with the first door it is used on
+        let mut walked = 0;
+        let mut small_keys: Vec<usize> = Vec::new();
+        let mut boss_keys: Vec<usize> = Vec::new();
+        let mut key_distances = Vec::new();
+        self.record_walk_through(true, |_, progress, distance| {
+
+            walked += distance;
+
+            match progress {
+                Progress::Collected(Key::Small) => small_keys.push(walked),
+                Progress::Collected(Key::Boss) => boss_keys.push(walked),
+                Progress::Unlocked(id) => {
+                    let keys = match self.doors[&id].lock {
+                        DoorLock::SmallKey => &mut small_keys,
+                        DoorLock::BossKey => &mut boss_keys,
+                        _ => return
+                    };
+                    if keys.len() > 0 {
+                        key_distances.push(walked - keys.remove(0));
+                    }
+                },
+                _ => {}
+            }
+
+        });
+
+        let critical_path = graph.path(entrance, exit).unwrap_or(Vec::new());
+
+        // Locks which require something to be collected first
+        let is_locked = |door: &Door| match door.lock {
+            DoorLock::SmallKey | DoorLock::BossKey | DoorLock::Item(_) => true,
+            _ => false
+        };
+
+        let critical_locks = critical_path.iter().zip(critical_path.iter().skip(1)).filter(|&(a, b)| {
+            self.door_between(*a, *b).map_or(false, |door| is_locked(door))
+
+        }).count();
+
+        // Secret rooms are counted on their own, everything else only
+        // describes the layout without them and their hidden doors
+        let rooms: Vec<(&Offset, &Room)> = self.rooms.iter().filter(|&(_, room)| {
+            room.typ != RoomType::Secret
+
+        }).collect();
+
+        let doors = self.doors.values().filter(|door| door.lock != DoorLock::Hidden).count();
+
+        let mut dead_ends = 0;
+        let mut branches = 0;
+        let mut onward_doors = 0;
+        for &(offset, room) in rooms.iter() {
+
+            let door_count = room.doors.iter().filter(|d| {
+                self.doors[&d.door].lock != DoorLock::Hidden
+
+            }).count();
+
+            if *offset == entrance {
+                branches += 1;
+                onward_doors += door_count;
+
+            } else if door_count > 1 {
+                branches += 1;
+                onward_doors += door_count - 1;
+
+            } else if *offset != exit {
+                dead_ends += 1;
+            }
+
+        }
+
+        let content = rooms.iter().filter(|&&(_, room)| room.is_empty() == false).count();
+        Metrics {
+            rooms: rooms.len(),
+            secret_rooms: self.rooms.len() - rooms.len(),
+            critical_path: critical_path.len(),
+            greedy_backtracking: walked.saturating_sub(critical_path.len().saturating_sub(1)),
+            branching_factor: onward_doors as f32 / cmp::max(branches, 1) as f32,
+            dead_ends: dead_ends,
+            loops: (doors + 1).saturating_sub(rooms.len()),
+            locked_doors: self.doors.values().filter(|door| is_locked(door)).count(),
+            critical_locks: critical_locks,
+            key_distances: key_distances,
+            content_ratio: content as f32 / cmp::max(rooms.len(), 1) as f32
+        }
+
+    }
+
+    fn walk_through(&self) -> (bool, HashMap<Offset, usize>) {
+        self.record_walk_through(false, |_, _, _| {})
+    }
+
+    // Reports every event of the walk through, when measuring also along
+    // with the number of rooms walked since the previous one, the measured
+    // walk greedily heads for the closest room with something to do next
+    fn record_walk_through<F>(
+        &self, measure: bool, mut record: F
+
+    ) -> (bool, HashMap<Offset, usize>) where F: FnMut(Offset, Progress, usize) {
+
+        let mut steps: HashMap<Offset, usize> = HashMap::new();
+        let entrance = match self.entrance_room {
+            Some(offset) => offset,
+            None => return (false, steps)
+        };
+
+        // Rooms reached so far in the order they were found, along with the
+        // ones which may still have something left to interact with and the
+        // locked doors leading out of them
+        let mut visited: HashSet<(Offset, Environment)> = HashSet::new();
+        let mut environments: Vec<Environment> = Vec::new();
+        let mut rooms: HashSet<Offset> = HashSet::new();
+        let mut pending: Vec<Offset> = Vec::new();
+        let mut locks: Vec<(Offset, &Door)> = Vec::new();
+        let mut interacted: HashSet<EntityId> = HashSet::new();
+
+        // Walk through the dungeon until we either reach the exit or cannot
+        // make any more progress, remembering at which step each room was
+        // first reached
+        let mut state = State::new();
+        let mut step = 0;
+        let mut search = true;
+        let mut position = (entrance, state.environment);
+        loop {
+
+            // Opening doors only ever adds to the rooms we can reach, so we
+            // continue the previous search from them, anything else requires
+            // searching again from the entrance
+            let changes = state.take_changes();
+            search = search || changes.iter().any(|change| match *change {
+                Change::Opened(_) | Change::Chest(_) => false,
+                _ => true
+            });
+
+            // Spawned chests can appear in rooms which were already done
+            for change in changes.iter() {
+                if let Change::Chest(offset) = *change {
+                    if rooms.contains(&offset) && pending.contains(&offset) == false {
+                        pending.push(offset);
+                    }
+                }
+            }
+
+            let mut to_visit: VecDeque<(Offset, Environment)> = VecDeque::new();
+            if search {
+                visited.clear();
+                environments.clear();
+                rooms.clear();
+                pending.clear();
+                locks.clear();
+                visited.insert((entrance, state.environment));
+                to_visit.push_back((entrance, state.environment));
+                search = false;
+
+            } else {
+                for change in changes.iter() {
+                    if let Change::Opened(id) = *change {
+                        let door = self.doors.get(&id).unwrap();
+                        let (a, b) = door.rooms;
+                        for &(from, to) in [(a, b), (b, a)].iter() {
+                            for environment in environments.iter() {
+                                if visited.contains(&(from, *environment))
+                                    && state.is_door_open_in(self, from, id, *environment)
+                                    && visited.insert((to, *environment)) {
+
+                                    to_visit.push_back((to, *environment));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Find all new rooms we can reach with the doors opened so far,
+            // in any crystal colour and water level, hidden doors are found by
+            // searching the walls of every room which is reached
+            for (offset, environment) in self.expand_states(&mut visited, to_visit, |room, door, environment| {
+                door.lock == DoorLock::Hidden || state.is_door_open_in(self, room.offset, door.id, environment)
+
+            }, |id, warp| {
+                state.is_warp_active(id, warp)
+
+            }) {
+
+                if environments.contains(&environment) == false {
+                    environments.push(environment);
+                }
+
+                if rooms.insert(offset) {
+                    steps.entry(offset).or_insert(step);
+                    pending.push(offset);
+
+                    let room = self.rooms.get(&offset).unwrap();
+                    for d in room.doors.iter() {
+                        let door = self.doors.get(&d.door).unwrap();
+                        match door.lock {
+                            DoorLock::SmallKey | DoorLock::BossKey | DoorLock::Item(_) => {
+                                if state.is_door_unlocked(door.id) == false {
+                                    locks.push((offset, door));
+                                }
+                            },
+                            DoorLock::Hidden => {
+                                state.reveal_door(self, door.id);
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+
+            }
+
+            if let Some(exit) = self.exit_room.filter(|offset| rooms.contains(offset)) {
+                let (walked, _) = self.walk_to(measure, &state, position, exit);
+                record(exit, Progress::Exited, walked);
+                return (true, steps);
+            }
+
+            // Interact with everything inside the reachable rooms, chests
+            // spawned by triggers become available right away
+            let mut progress = false;
+            // Measuring only walks to rooms where something can be done right
+            // now, the others are left for the next step
+            let mut remaining: HashSet<Offset> = pending.iter().cloned().filter(|offset| {
+                measure && ((state.spawned_chests(*offset) > 0 && state.is_room_lit(self, *offset))
+                    || self.rooms.get(offset).unwrap().entities.iter().any(|&(id, ref entity)| {
+                        Dungeon::is_interactable(entity)
+                            && interacted.contains(&id) == false
+                            && state.can_interact(self, *offset, id)
+                    }))
+
+            }).collect();
+            let mut index = 0;
+            loop {
+
+                // When measuring, the closest of the remaining rooms is
+                // visited next, the route has to be taken before interacting
+                // as that might change which doors are open
+                let (offset, mut arrival) = if measure && remaining.len() > 0 {
+                    match self.closest_route(&state, position, &remaining) {
+                        Some((offset, length, next)) => (offset, Some((length, next))),
+
+                        // Rooms which cannot be reached from where the walk
+                        // is right now are visited without walking anywhere
+                        None => (sorted_offsets(remaining.iter().cloned())[0], Some((0, position)))
+                    }
+
+                } else if measure == false && index < pending.len() {
+                    index += 1;
+                    (pending[index - 1], Some((0, position)))
+
+                } else {
+                    break;
+                };
+
+                remaining.remove(&offset);
+
+                // Only an actual event in the room moves the walk there
+                let room = self.rooms.get(&offset).unwrap();
+                for &(id, ref entity) in room.entities.iter() {
+
+                    if Dungeon::is_interactable(entity) == false || interacted.contains(&id) {
+                        continue;
+                    }
+
+                    let keys = (state.small_keys, state.boss_key);
+                    let environment = state.environment;
+                    if state.interact(self, offset, id) {
+                        let walked = Dungeon::arrive(&mut arrival, &mut position);
+                        record(offset, Dungeon::collected(keys, &state), walked);
+                        interacted.insert(id);
+                        progress = true;
+
+                        // Switches can change the environment for good
+                        if state.environment != environment {
+                            position.1 = state.environment;
+                        }
+                    }
+                }
+
+                let keys = (state.small_keys, state.boss_key);
+                while state.open_spawned_chest(self, offset) {
+                    let walked = Dungeon::arrive(&mut arrival, &mut position);
+                    record(offset, Dungeon::collected(keys, &state), walked);
+                    progress = true;
+                }
+
+            }
+
+            // Entities can only be interacted with once, so rooms which have
+            // none left are done unless a chest gets spawned in them later on
+            pending.retain(|offset| {
+                state.spawned_chests(*offset) > 0 || self.rooms.get(offset).unwrap().entities.iter().any(|&(id, ref entity)| {
+                    Dungeon::is_interactable(entity) && interacted.contains(&id) == false
+                })
+            });
+
+            // Once nothing else is left, use up keys one door at a time
+            if progress == false {
+                let unlocked = locks.iter().cloned().find(|&(_, door)| {
+                    state.can_unlock_door(self, door.id)
+                });
+
+                if let Some((offset, unlocked)) = unlocked {
+                    let (walked, next) = self.walk_to(measure, &state, position, offset);
+                    state.unlock_door(self, unlocked.id);
+                    position = next;
+                    record(offset, Progress::Unlocked(unlocked.id), walked);
+                    locks.retain(|&(_, door)| door.id != unlocked.id);
+                    progress = true;
+                }
+            }
+
+            if progress == false {
+                return (false, steps);
+            }
+
+            step += 1;
+
+        }
+
+    }
+
+    fn arrive(arrival: &mut Option<(usize, (Offset, Environment))>, position: &mut (Offset, Environment)) -> usize {
+        match arrival.take() {
+            Some((length, next)) => {
+                *position = next;
+                length
+            },
+            None => 0
+        }
+    }
+
+    fn walk_to(
+        &self, measure: bool, state: &State, position: (Offset, Environment), to: Offset
+
+    ) -> (usize, (Offset, Environment)) {
+        if measure == false {
+            return (0, position);
+        }
+        match self.route_length(state, position, to) {
+            Some((length, environment)) => (length, (to, environment)),
+            None => (0, position)
+        }
+    }
+
+    fn is_interactable(entity: &Entity) -> bool {
+        match *entity {
+            // Crystal colours and water levels are already covered by the
+            // search for reachable rooms
+            Entity::Crystal(_) => false,
+            Entity::Switch(ref switch) => switch.is_repeatable() == false,
+            Entity::Torch(ref torch) => torch.lit == false,
+            Entity::Trap(_) | Entity::Warp(_) => false,
+            Entity::Chest(_) | Entity::Enemy(_) | Entity::Pot(_) => true
+        }
+    }
+
+    fn collected(keys: (usize, bool), state: &State) -> Progress {
+        if state.small_keys > keys.0 {
+            Progress::Collected(Key::Small)
+
+        } else if state.boss_key && keys.1 == false {
+            Progress::Collected(Key::Boss)
+
+        } else {
+            Progress::Interacted
+        }
+    }
+
+
+    // Room collection methods ------------------------------------------------
+
+    fn door_between_mut(&mut self, a: Offset, b: Offset) -> Option<&mut Door> {
+        let id = match self.rooms.get(&a).and_then(|room| room.connection_to(&b)) {
+            Some(connection) => connection.door,
+            None => return None
+        };
+        self.doors.get_mut(&id)
+    }
+
+    pub(crate) fn empty_rooms(&self) -> Vec<Offset> {
+
+        let mut empty_rooms: Vec<Offset> = Vec::new();
+
+        for (offset, room) in self.rooms.iter() {
+            if room.typ == RoomType::Exit {
+                continue;
+
+            } else if room.is_empty() == false {
+                continue;
+
+            } else {
+                empty_rooms.push(*offset);
+            }
+        }
+
+        sorted_offsets(empty_rooms)
 
     }
 
 
     // Path related methods ---------------------------------------------------
 
+    fn expand_states<F, W>(
+        &self,
+        visited: &mut HashSet<(Offset, Environment)>,
+        mut to_visit: VecDeque<(Offset, Environment)>,
+        callback: F,
+        warp_callback: W
+
+    ) -> Vec<(Offset, Environment)> where F : Fn(&Room, &Door, Environment) -> bool, W : Fn(EntityId, &Warp) -> bool {
+
+        // Continue searching from states which were already marked as visited
+        // and return all states reached from them
+        let mut states = Vec::new();
+        while let Some((offset, environment)) = to_visit.pop_front() {
+
+            // Get next room and environment to visit
+            let room = self.rooms.get(&offset).unwrap();
+            states.push((offset, environment));
+
+            let mut next = Vec::new();
+            for d in room.doors.iter() {
+                if callback(&room, self.doors.get(&d.door).unwrap(), environment) == true {
+                    next.push((d.to, environment));
+                }
+            }
+
+            for &(id, warp) in room.warps().iter() {
+                if warp_callback(id, warp) == true {
+                    next.push((self.warp_target(warp), environment));
+                }
+            }
+
+            for environment in Dungeon::environment_changes(room, environment) {
+                next.push((offset, environment));
+            }
+
+            for state in next.into_iter() {
+                if visited.insert(state) {
+                    to_visit.push_back(state);
+                }
+            }
+
+        }
+
+        states
+
+    }
+
+    fn environment_changes(room: &Room, environment: Environment) -> Vec<Environment> {
+
+        // Crystals allow toggling the colour without leaving the room
+        let mut changes = Vec::new();
+        if room.crystals().len() > 0 {
+            changes.push(Environment {
+                crystal: environment.crystal.toggled(),
+                water_level: environment.water_level
+            });
+        }
+
+        // And so do water level switches for the water level
+        for &(_, switch) in room.switches().iter().filter(|&&(_, s)| s.is_repeatable()) {
+            for trigger in switch.triggers.iter() {
+                if let Trigger::SetWaterLevel(level) = *trigger {
+                    changes.push(Environment {
+                        crystal: environment.crystal,
+                        water_level: level
+                    });
+                }
+            }
+        }
+
+        changes
+
+    }
+
+    fn route_length(
+        &self, state: &State, from: (Offset, Environment), to: Offset
+
+    ) -> Option<(usize, Environment)> {
+        let mut rooms = HashSet::new();
+        rooms.insert(to);
+        self.closest_route(state, from, &rooms).map(|(_, length, (_, environment))| {
+            (length, environment)
+        })
+    }
+
+    fn closest_route(
+        &self, state: &State, from: (Offset, Environment), rooms: &HashSet<Offset>
+
+    ) -> Option<(Offset, usize, (Offset, Environment))> {
+
+
+        // Walking through a door or warp takes one room, while changing the
+        // environment inside a room is free, so those are searched first
+        let mut lengths: HashMap<(Offset, Environment), usize> = HashMap::new();
+        let mut to_visit: VecDeque<((Offset, Environment), usize)> = VecDeque::new();
+        lengths.insert(from, 0);
+        to_visit.push_back((from, 0));
+
+        while let Some(((offset, environment), length)) = to_visit.pop_front() {
+
+            if lengths.get(&(offset, environment)).map_or(false, |l| *l < length) {
+                continue;
+            }
+
+            if rooms.contains(&offset) {
+                return Some((offset, length, (offset, environment)));
+            }
+
+            let room = self.rooms.get(&offset).unwrap();
+            let mut next = Vec::new();
+            for environment in Dungeon::environment_changes(room, environment) {
+                next.push(((offset, environment), 0));
+            }
+
+            for d in room.doors.iter() {
+                if state.is_door_open_in(self, offset, d.door, environment) {
+                    next.push(((d.to, environment), 1));
+                }
+            }
+
+            for &(id, warp) in room.warps().iter() {
+                if state.is_warp_active(id, warp) {
+                    next.push(((self.warp_target(warp), environment), 1));
+                }
+            }
+
+            for (state, cost) in next.into_iter() {
+                let length = length + cost;
+                if lengths.get(&state).map_or(true, |l| length < *l) {
+                    lengths.insert(state, length);
+                    if cost == 0 {
+                        to_visit.push_front((state, length));
+
+                    } else {
+                        to_visit.push_back((state, length));
+                    }
+                }
+            }
+
+        }
+
+        None
+
+    }
+
+    fn is_special_room(&self, offset: Offset) -> bool {
+        Some(offset) == self.boss_room
+            || Some(offset) == self.miniboss_room
+            || Some(offset) == self.exit_room
+    }
+
     fn find_room_path<F>(
         &self, start: Offset, callback: F
 
-    ) -> Option<RoomPath> where F : Fn(&Room, &RoomPath) -> bool {
+    ) -> Option<RoomPath> where F : Fn(&Room) -> bool {
+        let (tree, found) = self.search_rooms(start, callback);
+        found.map(|offset| tree.path(offset))
+    }
+
+    fn room_tree(&self, start: Offset) -> RoomTree {
+        let (tree, _) = self.search_rooms(start, |_| false);
+        tree
+    }
+
+    fn search_rooms<F>(
+        &self, start: Offset, callback: F
+
+    ) -> (RoomTree, Option<Offset>) where F : Fn(&Room) -> bool {
+
+        // Remember the room from which each room was first reached instead of
+        // copying the whole path for every one of them
+        let mut tree = RoomTree {
+            parents: HashMap::new(),
+            order: HashMap::new(),
+            depths: HashMap::new()
+        };
 
-        let mut to_path = RoomPath::new();
-        to_path.push(start);
+        let mut to_visit: VecDeque<Offset> = VecDeque::new();
+        to_visit.push_back(start);
+        tree.parents.insert(start, start);
+        tree.depths.insert(start, 0);
 
-        let mut visited: HashMap<Offset, bool> = HashMap::new();
-        let mut to_visit: Vec<(Offset, RoomPath)> = vec![(start, to_path)];
-        while to_visit.len() > 0 {
+        while let Some(offset) = to_visit.pop_front() {
 
             // Add current room to visited list
-            let (offset, path) = to_visit.remove(0);
             let room = self.rooms.get(&offset).unwrap();
-            visited.insert(offset, true);
+            let index = tree.order.len();
+            let depth = *tree.depths.get(&offset).unwrap();
+            tree.order.insert(offset, index);
 
-            // Invoke callback and return the path if it returns true
-            if callback(&room, &path) == true {
-                return Some(path);
+            // Invoke callback and return the room if it returns true
+            if callback(&room) == true {
+                return (tree, Some(offset));
             }
 
-            // Add all connected rooms to the to_visit list
-            for d in room.doors.iter() {
-                if visited.contains_key(&d.to) == false  {
-                    let mut to_path = path.clone();
-                    to_path.push(d.to);
-                    to_visit.push((d.to, to_path));
-                    visited.insert(d.to, true);
+            // Add all connected rooms to the to_visit list, one-way doors can
+            // only be walked through from their far side
+            let doors = room.doors.iter().filter(|d| {
+                self.doors.get(&d.door).unwrap().is_passable_from(offset)
+
+            }).map(|d| d.to);
+
+            // And follow warps to rooms which are not adjacent, as long as
+            // they do not need to be triggered first
+            let warps = room.active_warps().into_iter().map(|(_, warp)| self.warp_target(warp));
+
+            for to in doors.chain(warps) {
+                if tree.parents.contains_key(&to) == false {
+                    tree.parents.insert(to, offset);
+                    tree.depths.insert(to, depth + 1);
+                    to_visit.push_back(to);
                 }
             }
 
         }
 
-        None
+        (tree, None)
 
     }
 
-    fn boss_key_path(&self) -> RoomPath {
+    fn room_distances(&self, sources: &[Offset]) -> HashMap<Offset, usize> {
 
-        // Get path from entrance to boss key
-        self.find_room_path(self.entrance_room.unwrap(), |room, _| {
-            if let Some(ref chest) = room.chest {
-                match &chest.item {
-                    &Item::Key(ref key_typ) => {
-                        *key_typ == Key::Boss
-                    },
-                    _ => false
-                }
+        // Breadth first search from all sources at once
+        let mut distances: HashMap<Offset, usize> = HashMap::new();
+        let mut to_visit: VecDeque<Offset> = VecDeque::new();
+        for offset in sources.iter() {
+            if distances.insert(*offset, 0).is_none() {
+                to_visit.push_back(*offset);
+            }
+        }
 
-            } else {
-                false
+        while let Some(offset) = to_visit.pop_front() {
+
+            let distance = *distances.get(&offset).unwrap();
+            let room = self.rooms.get(&offset).unwrap();
+
+            let doors = room.doors.iter().filter(|d| {
+                self.doors.get(&d.door).unwrap().is_passable_from(offset)
+
+            }).map(|d| d.to);
+
+            let warps = room.active_warps().into_iter().map(|(_, warp)| self.warp_target(warp));
+
+            for to in doors.chain(warps) {
+                if distances.contains_key(&to) == false {
+                    distances.insert(to, distance + 1);
+                    to_visit.push_back(to);
+                }
             }
 
+        }
+
+        distances
+
+    }
+
+    fn warp_target(&self, warp: &Warp) -> Offset {
+        *self.room_ids.get(&warp.to).unwrap()
+    }
+
+    fn is_next_to(&self, offset: Offset, rooms: &HashSet<Offset>) -> bool {
+        let room = self.rooms.get(&offset).unwrap();
+        rooms.contains(&offset) || room.doors.iter().any(|d| {
+            rooms.contains(&d.to) && self.doors.get(&d.door).unwrap().is_passable_from(offset)
+
+        }) || room.active_warps().iter().any(|&(_, warp)| rooms.contains(&self.warp_target(warp)))
+    }
+
+    fn boss_key_path(&self) -> RoomPath {
+
+        // Get path from entrance to boss key
+        self.find_room_path(self.entrance_room.unwrap(), |room| {
+            room.chests().iter().any(|&(_, chest)| {
+                chest.item == Item::Key(Key::Boss)
+            })
+
         }).unwrap()
 
     }
@@ -740,13 +3037,10 @@ impl Dungeon {
     fn boss_door_path(&self) -> RoomPath {
 
         // Get path from entrance to boss room
-        self.find_room_path(self.entrance_room.unwrap(), |room, _| {
-            if let Some(ref enemy) = room.enemy {
+        self.find_room_path(self.entrance_room.unwrap(), |room| {
+            room.enemies().iter().any(|&(_, enemy)| {
                 enemy.typ == EnemyType::Boss
-
-            } else {
-                false
-            }
+            })
 
         }).unwrap()
 
@@ -755,24 +3049,16 @@ impl Dungeon {
     fn end_room_paths(&mut self) -> Vec<RoomPath> {
 
         // Collect all end rooms
-        let mut end_rooms = Vec::new();
-        for (offset, room) in self.rooms.iter_mut() {
-            if room.typ == RoomType::End {
-                end_rooms.push(*offset);
-            }
-        }
+        let end_rooms = sorted_offsets(self.rooms.iter().filter(|&(_, room)| {
+            room.typ == RoomType::End
 
-        // HashMap iteration order is random, but we need the rooms to be
-        // deterministic
-        end_rooms.sort_by(|a, b| {
-            (a.x + a.y * 1000).cmp(&(b.x + b.y * 1000))
-        });
+        }).map(|(offset, _)| *offset));
 
         let mut paths: Vec<RoomPath> = Vec::new();
         for offset in end_rooms.iter() {
 
             // Collect paths from all end rooms to the first intersection
-            let path = self.find_room_path(*offset, |room, _| {
+            let path = self.find_room_path(*offset, |room| {
 
                 // TODO this does not handle cases where there are no
                 // intersections but just one linear dungeon hallway
@@ -794,3 +3080,435 @@ impl Dungeon {
 
 }
 
+
+#[cfg(test)]
+impl Dungeon {
+
+    // Builds a dungeon by hand from its rooms and the doors between them, the
+    // first room is the entrance and the last one the exit
+    pub fn from_rooms(rooms: &[(i32, i32)], doors: &[((i32, i32), (i32, i32), DoorLock)]) -> Dungeon {
+
+        let mut dungeon = Dungeon::new();
+        for &(x, y) in rooms.iter() {
+            let room = Room::new(dungeon.ids.room(), x, y);
+            dungeon.room_ids.insert(room.id, room.offset);
+            dungeon.rooms.insert(room.offset, room);
+        }
+
+        for &((ax, ay), (bx, by), ref lock) in doors.iter() {
+            dungeon.connect_rooms(Offset { x: ax, y: ay }, Offset { x: bx, y: by }, lock.clone());
+        }
+
+        let entrance = Offset { x: rooms[0].0, y: rooms[0].1 };
+        let exit = Offset { x: rooms[rooms.len() - 1].0, y: rooms[rooms.len() - 1].1 };
+        dungeon.rooms.get_mut(&entrance).unwrap().typ = RoomType::Entrance;
+        dungeon.rooms.get_mut(&exit).unwrap().typ = RoomType::Exit;
+        dungeon.entrance_room = Some(entrance);
+        dungeon.exit_room = Some(exit);
+        Dungeon::set_room_types(&mut dungeon.rooms);
+        dungeon
+
+    }
+
+    pub fn add_entity(&mut self, (x, y): (i32, i32), entity: Entity) -> EntityId {
+        let id = self.ids.entity();
+        self.rooms.get_mut(&Offset { x: x, y: y }).unwrap().add_entity(id, entity);
+        id
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use rand::{SeedableRng, StdRng};
+
+    use base::Offset;
+    use entity::Entity;
+    use entity::chest::Chest;
+    use entity::crystal::{Crystal, Color};
+    use entity::enemy::{Enemy, Type as EnemyType};
+    use entity::item::{Item, Key};
+    use entity::pot::Pot;
+    use entity::switch::Switch;
+    use entity::torch::Torch;
+    use entity::trigger::Trigger;
+    use entity::warp::Warp;
+    use room::Type as RoomType;
+    use room::door::Lock as DoorLock;
+    use session::Phase;
+    use super::{Dungeon, Monitor, Pass};
+
+    fn offset(x: i32, y: i32) -> Offset {
+        Offset { x: x, y: y }
+    }
+
+    fn warp_dungeon(active: bool) -> Dungeon {
+
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (5, 5)], &[
+            ((0, 0), (1, 0), DoorLock::None)
+        ]);
+
+        let to = dungeon.rooms[&offset(5, 5)].id;
+        dungeon.add_entity((1, 0), Entity::Warp(Warp {
+            to: to,
+            active: active
+        }));
+
+        dungeon
+
+    }
+
+    #[test]
+    fn searches_only_follow_active_warps() {
+
+        let dungeon = warp_dungeon(false);
+        assert_eq!(dungeon.room_distances(&[offset(0, 0)]).get(&offset(5, 5)), None);
+        assert!(dungeon.find_room_path(offset(0, 0), |room| room.offset == offset(5, 5)).is_none());
+
+        let dungeon = warp_dungeon(true);
+        assert_eq!(dungeon.room_distances(&[offset(0, 0)]).get(&offset(5, 5)), Some(&2));
+        let path = dungeon.find_room_path(offset(0, 0), |room| room.offset == offset(5, 5)).unwrap();
+        assert_eq!(*path, vec![offset(0, 0), offset(1, 0), offset(5, 5)]);
+
+    }
+
+
+    #[test]
+    fn walk_through_reveals_hidden_doors() {
+
+        // E -x- X with the only key hidden in a secret room next to E
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (0, 1), (1, 0)], &[
+            ((0, 0), (0, 1), DoorLock::Hidden),
+            ((0, 0), (1, 0), DoorLock::SmallKey)
+        ]);
+
+        dungeon.rooms.get_mut(&offset(0, 1)).unwrap().typ = RoomType::Secret;
+        dungeon.add_entity((0, 1), Entity::Chest(Chest {
+            item: Item::Key(Key::Small)
+        }));
+
+        let (valid, steps) = dungeon.walk_through();
+        assert!(valid);
+        assert_eq!(steps.get(&offset(0, 1)), Some(&0));
+        assert_eq!(steps.get(&offset(1, 0)), Some(&2));
+
+    }
+
+
+    #[test]
+    fn walk_through_opens_chests_spawned_by_enemies_and_switches() {
+
+        // E -x- X with the key in a chest which only appears once the enemy
+        // in E is defeated or the switch in E is pressed
+        let spawners = vec![
+            Entity::Enemy(Enemy {
+                typ: EnemyType::Small,
+                weakness: Item::None,
+                triggers: vec![Trigger::Chest(Chest {
+                    item: Item::Key(Key::Small)
+                })]
+            }),
+            Entity::Switch(Switch {
+                triggers: vec![Trigger::Chest(Chest {
+                    item: Item::Key(Key::Small)
+                })]
+            })
+        ];
+
+        for spawner in spawners.into_iter() {
+
+            let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+                ((0, 0), (1, 0), DoorLock::SmallKey)
+            ]);
+            assert!(dungeon.walk_through().0 == false);
+
+            dungeon.add_entity((0, 0), spawner);
+            let (valid, steps) = dungeon.walk_through();
+            assert!(valid);
+            assert_eq!(steps.get(&offset(1, 0)), Some(&2));
+
+        }
+
+    }
+
+
+    #[test]
+    fn walk_through_follows_warps_once_they_were_triggered() {
+
+        let mut dungeon = warp_dungeon(false);
+        let warp = dungeon.rooms[&offset(1, 0)].warps()[0].0;
+        assert!(dungeon.walk_through().0 == false);
+
+        dungeon.add_entity((0, 0), Entity::Switch(Switch {
+            triggers: vec![Trigger::Warp(warp)]
+        }));
+        assert!(dungeon.walk_through().0);
+
+    }
+
+
+    #[test]
+    fn walk_through_needs_items_for_gates_and_weaknesses() {
+
+        // The hookshot opens the way to an enemy which drops the key for the
+        // exit, but can only be defeated with the bow found behind it
+        let layout = |bow: bool| {
+            let mut dungeon = Dungeon::from_rooms(&[(0, 0), (0, 1), (0, 2), (1, 0)], &[
+                ((0, 0), (0, 1), DoorLock::Item(Item::Hookshot)),
+                ((0, 1), (0, 2), DoorLock::None),
+                ((0, 0), (1, 0), DoorLock::SmallKey)
+            ]);
+
+            dungeon.add_entity((0, 0), Entity::Chest(Chest {
+                item: Item::Hookshot
+            }));
+            dungeon.add_entity((0, 1), Entity::Enemy(Enemy {
+                typ: EnemyType::Small,
+                weakness: Item::Bow,
+                triggers: vec![Trigger::Item(Item::Key(Key::Small))]
+            }));
+
+            if bow {
+                dungeon.add_entity((0, 2), Entity::Chest(Chest {
+                    item: Item::Bow
+                }));
+            }
+
+            dungeon
+        };
+
+        assert!(layout(false).walk_through().0 == false);
+
+        let (valid, steps) = layout(true).walk_through();
+        assert!(valid);
+        assert_eq!(steps.get(&offset(0, 2)), Some(&2));
+
+    }
+
+
+    #[test]
+    fn walk_through_only_passes_one_way_doors_from_their_far_side() {
+
+        let forwards = Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+            ((0, 0), (1, 0), DoorLock::OneWay(offset(0, 0)))
+        ]);
+        assert!(forwards.walk_through().0);
+
+        let backwards = Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+            ((0, 0), (1, 0), DoorLock::OneWay(offset(1, 0)))
+        ]);
+        assert!(backwards.walk_through().0 == false);
+
+        // A shortcut back to the entrance does not count as a way forward
+        let shortcut = Dungeon::from_rooms(&[(0, 0), (1, 0), (1, 1), (0, 1)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (1, 1), DoorLock::None),
+            ((1, 1), (0, 1), DoorLock::None),
+            ((0, 1), (0, 0), DoorLock::OneWay(offset(0, 1)))
+        ]);
+        let (valid, steps) = shortcut.walk_through();
+        assert!(valid);
+        assert_eq!(shortcut.room_distances(&[offset(0, 0)]).get(&offset(0, 1)), Some(&3));
+        assert_eq!(steps.get(&offset(0, 1)), Some(&0));
+
+    }
+
+
+    #[test]
+    fn walk_through_searches_every_crystal_colour() {
+
+        // E -red- A -blue- X, the barriers start out red so the crystal has
+        // to be hit once A was reached while they are still lowered
+        let layout = |crystal: (i32, i32)| {
+            let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (2, 0)], &[
+                ((0, 0), (1, 0), DoorLock::Barrier(Color::Red)),
+                ((1, 0), (2, 0), DoorLock::Barrier(Color::Blue))
+            ]);
+            dungeon.add_entity(crystal, Entity::Crystal(Crystal));
+            dungeon
+        };
+
+        assert!(layout((1, 0)).walk_through().0);
+        assert!(layout((0, 0)).walk_through().0 == false);
+
+    }
+
+
+    #[test]
+    fn walk_through_lights_dark_rooms_before_searching_them() {
+
+        // E -x- X with the key in a dark room D next to E
+        let layout = |light: Option<Entity>| {
+            let mut dungeon = Dungeon::from_rooms(&[(0, 0), (0, 1), (1, 0)], &[
+                ((0, 0), (0, 1), DoorLock::None),
+                ((0, 0), (1, 0), DoorLock::SmallKey)
+            ]);
+
+            dungeon.rooms.get_mut(&offset(0, 1)).unwrap().dark = true;
+            dungeon.add_entity((0, 1), Entity::Chest(Chest {
+                item: Item::Key(Key::Small)
+            }));
+
+            match light {
+                Some(Entity::Torch(torch)) => {
+                    dungeon.add_entity((0, 1), Entity::Torch(torch));
+                },
+                Some(lantern) => {
+                    dungeon.add_entity((0, 0), lantern);
+                },
+                None => {}
+            }
+
+            dungeon
+        };
+
+        assert!(layout(None).walk_through().0 == false);
+        assert!(layout(Some(Entity::Chest(Chest { item: Item::Lantern }))).walk_through().0);
+        assert!(layout(Some(Entity::Torch(Torch { lit: false }))).walk_through().0);
+
+    }
+
+
+    #[test]
+    fn walk_through_searches_every_water_level() {
+
+        // E -low- A -high- X, the water can only be raised once A is
+        // reached while it is still low
+        let layout = |switch: (i32, i32)| {
+            let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (2, 0)], &[
+                ((0, 0), (1, 0), DoorLock::Water(0)),
+                ((1, 0), (2, 0), DoorLock::Water(1))
+            ]);
+            dungeon.add_entity(switch, Entity::Switch(Switch {
+                triggers: vec![Trigger::SetWaterLevel(1)]
+            }));
+            dungeon
+        };
+
+        assert!(layout((1, 0)).walk_through().0);
+        assert!(layout((0, 0)).walk_through().0 == false);
+
+    }
+
+
+    #[test]
+    fn walk_through_breaks_pots_to_find_keys() {
+
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+            ((0, 0), (1, 0), DoorLock::SmallKey)
+        ]);
+        dungeon.add_entity((0, 0), Entity::Pot(Pot {
+            item: Item::None
+        }));
+        assert!(dungeon.walk_through().0 == false);
+
+        dungeon.add_entity((0, 0), Entity::Pot(Pot {
+            item: Item::Key(Key::Small)
+        }));
+        assert!(dungeon.walk_through().0);
+
+    }
+
+
+    #[test]
+    fn walk_through_scales_to_long_dungeons() {
+
+        // A corridor of twenty thousand rooms split up by a locked door every
+        // two thousand rooms, with each key lying right in front of its door
+        let rooms: Vec<(i32, i32)> = (0..20000).map(|x| (x, 0)).collect();
+        let doors: Vec<((i32, i32), (i32, i32), DoorLock)> = (1..20000).map(|x| {
+            ((x - 1, 0), (x, 0), if x % 2000 == 0 { DoorLock::SmallKey } else { DoorLock::None })
+
+        }).collect();
+
+        let mut dungeon = Dungeon::from_rooms(&rooms, &doors);
+        for x in 1..10 {
+            dungeon.add_entity((x * 2000 - 1, 0), Entity::Chest(Chest {
+                item: Item::Key(Key::Small)
+            }));
+        }
+
+        // Every lock takes one step to find its key and one to unlock it
+        let (valid, steps) = dungeon.walk_through();
+        assert!(valid);
+        assert_eq!(steps.get(&offset(19999, 0)), Some(&18));
+
+        let metrics = dungeon.metrics();
+        assert_eq!(metrics.critical_path, 20000);
+        assert_eq!(metrics.greedy_backtracking, 0);
+        assert_eq!(metrics.key_distances, vec![0; 9]);
+
+    }
+
+    static RETRIED_RUNS: AtomicUsize = AtomicUsize::new(0);
+    static BACKTRACKED_RUNS: AtomicUsize = AtomicUsize::new(0);
+    static FAILING_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    fn add_pot(dungeon: &mut Dungeon) {
+        dungeon.add_entity((0, 0), Entity::Pot(Pot {
+            item: Item::None
+        }));
+    }
+
+    fn pot_count(dungeon: &Dungeon) -> usize {
+        dungeon.rooms[&offset(0, 0)].pots().len()
+    }
+
+    fn failing_twice(dungeon: &mut Dungeon, _: &mut StdRng) -> bool {
+        add_pot(dungeon);
+        RETRIED_RUNS.fetch_add(1, AtomicOrdering::SeqCst) >= 2
+    }
+
+    fn backtracked_into(dungeon: &mut Dungeon, _: &mut StdRng) -> bool {
+        add_pot(dungeon);
+        BACKTRACKED_RUNS.fetch_add(1, AtomicOrdering::SeqCst);
+        true
+    }
+
+    fn failing_until_backtracked(_: &mut Dungeon, _: &mut StdRng) -> bool {
+        BACKTRACKED_RUNS.load(AtomicOrdering::SeqCst) > 1
+    }
+
+    fn always_failing(_: &mut Dungeon, _: &mut StdRng) -> bool {
+        FAILING_RUNS.fetch_add(1, AtomicOrdering::SeqCst);
+        false
+    }
+
+    fn run_passes(passes: &[(Pass, Phase, bool)]) -> (bool, Dungeon) {
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None)
+        ]);
+        let mut rng: StdRng = SeedableRng::from_seed(&[1, 2, 3][..]);
+        let done = dungeon.run_passes(&mut rng, passes, &mut Monitor::none());
+        (done, dungeon)
+    }
+
+    #[test]
+    fn failed_passes_are_retried_from_a_snapshot() {
+        let (done, dungeon) = run_passes(&[(failing_twice, Phase::Features, false)]);
+        assert!(done);
+        assert_eq!(RETRIED_RUNS.load(AtomicOrdering::SeqCst), 3);
+        assert_eq!(pot_count(&dungeon), 1);
+    }
+
+    #[test]
+    fn passes_which_keep_failing_backtrack_into_the_one_before() {
+        let (done, dungeon) = run_passes(&[
+            (backtracked_into, Phase::Features, false),
+            (failing_until_backtracked, Phase::Features, false)
+        ]);
+        assert!(done);
+        assert_eq!(BACKTRACKED_RUNS.load(AtomicOrdering::SeqCst), 2);
+        assert_eq!(pot_count(&dungeon), 1);
+    }
+
+    #[test]
+    fn the_first_pass_cannot_be_backtracked_out_of() {
+        let (done, _) = run_passes(&[(always_failing, Phase::Features, false)]);
+        assert!(done == false);
+        assert_eq!(FAILING_RUNS.load(AtomicOrdering::SeqCst), 3);
+    }
+
+}
@@ -1,6 +1,6 @@
 use entity::item::Item;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Chest {
     pub item: Item
 }
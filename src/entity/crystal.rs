@@ -0,0 +1,24 @@
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Color {
+    Red,
+    Blue
+}
+
+impl Color {
+    pub fn toggled(&self) -> Color {
+        match *self {
+            Color::Red => Color::Blue,
+            Color::Blue => Color::Red
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Crystal;
+
+impl Crystal {
+    pub fn to_string(&self) -> String {
+        "*".to_owned()
+    }
+}
+
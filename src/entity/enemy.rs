@@ -1,26 +1,39 @@
+use entity::item::Item;
 use entity::trigger::Trigger;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Type {
     Small,
     Big,
     Boss
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Enemy {
     pub typ: Type,
+    pub weakness: Item,
     pub triggers: Vec<Trigger>
 }
 
 impl Enemy {
     pub fn to_string(&self) -> String {
-        format!(
-            "E({:?},{:?})",
-            self.typ,
-            self.triggers
+        if self.weakness == Item::None {
+            format!(
+                "E({:?},{:?})",
+                self.typ,
+                self.triggers
 
-        ).to_owned()
+            ).to_owned()
+
+        } else {
+            format!(
+                "E({:?},{:?},{:?})",
+                self.typ,
+                self.weakness,
+                self.triggers
+
+            ).to_owned()
+        }
     }
 }
 
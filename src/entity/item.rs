@@ -1,15 +1,20 @@
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Key {
     Small,
     Boss,
     None
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Item {
     Key(Key),
     Compass,
     Map,
+    Hookshot,
+    Bow,
+    Bombs,
+    Lantern,
+    Currency(usize),
     None
 }
 
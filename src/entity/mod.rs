@@ -1,6 +1,47 @@
 pub mod chest;
+pub mod crystal;
 pub mod enemy;
 pub mod item;
+pub mod pot;
 pub mod switch;
+pub mod torch;
+pub mod trap;
 pub mod trigger;
+pub mod warp;
+
+use self::chest::Chest;
+use self::crystal::Crystal;
+use self::enemy::Enemy;
+use self::pot::Pot;
+use self::switch::Switch;
+use self::torch::Torch;
+use self::trap::Trap;
+use self::warp::Warp;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Entity {
+    Chest(Chest),
+    Crystal(Crystal),
+    Enemy(Enemy),
+    Pot(Pot),
+    Switch(Switch),
+    Torch(Torch),
+    Trap(Trap),
+    Warp(Warp)
+}
+
+impl Entity {
+    pub fn to_string(&self) -> String {
+        match *self {
+            Entity::Chest(ref chest) => chest.to_string(),
+            Entity::Crystal(ref crystal) => crystal.to_string(),
+            Entity::Enemy(ref enemy) => enemy.to_string(),
+            Entity::Pot(ref pot) => pot.to_string(),
+            Entity::Switch(ref switch) => switch.to_string(),
+            Entity::Torch(ref torch) => torch.to_char().to_string(),
+            Entity::Trap(ref trap) => trap.to_string(),
+            Entity::Warp(ref warp) => warp.to_string()
+        }
+    }
+}
 
@@ -0,0 +1,16 @@
+use entity::item::Item;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Pot {
+    pub item: Item
+}
+
+impl Pot {
+    pub fn to_string(&self) -> String {
+        match self.item {
+            Item::None => "P".to_owned(),
+            ref item => format!("P({:?})", item).to_owned()
+        }
+    }
+}
+
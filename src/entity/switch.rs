@@ -1,13 +1,22 @@
 use entity::trigger::Trigger;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Switch {
     pub triggers: Vec<Trigger>
 }
 
 impl Switch {
+
+    pub fn is_repeatable(&self) -> bool {
+        self.triggers.len() > 0 && self.triggers.iter().all(|t| match *t {
+            Trigger::SetWaterLevel(_) => true,
+            _ => false
+        })
+    }
+
     pub fn to_string(&self) -> String {
         format!("S({:?})", self.triggers).to_owned()
     }
+
 }
 
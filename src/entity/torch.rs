@@ -0,0 +1,15 @@
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Torch {
+    pub lit: bool
+}
+
+impl Torch {
+    pub fn to_char(&self) -> char {
+        if self.lit {
+            'I'
+
+        } else {
+            'i'
+        }
+    }
+}
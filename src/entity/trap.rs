@@ -0,0 +1,30 @@
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Type {
+    Spikes,
+    FallingFloor,
+    Blade
+}
+
+impl Type {
+    pub fn to_char(&self) -> char {
+        match *self {
+            Type::Spikes => '^',
+            Type::FallingFloor => 'o',
+            Type::Blade => 'x'
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Trap {
+    pub typ: Type,
+    pub damage: usize,
+    pub speed: usize
+}
+
+impl Trap {
+    pub fn to_string(&self) -> String {
+        format!("{}{}", self.typ.to_char(), self.damage).to_owned()
+    }
+}
+
@@ -1,13 +1,35 @@
-use base::Offset;
+use base::{RoomId, DoorId, EntityId};
 use entity::item::Item;
 use entity::chest::Chest;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Trigger {
-    LockDoor(Offset),
-    OpenDoor(Offset),
+    LockDoor(DoorId),
+    OpenDoor(DoorId),
     Chest(Chest),
-    // TODO allow warp creation?
-    Item(Item)
+    Warp(EntityId),
+    Item(Item),
+    SetWaterLevel(usize)
 }
 
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Condition {
+    EnemiesDefeated(RoomId),
+    SwitchPressed(EntityId),
+    TorchesLit(RoomId),
+    ChestOpened(EntityId),
+    All(Vec<Condition>),
+    Any(Vec<Condition>)
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TriggerSet {
+    pub condition: Condition,
+    pub triggers: Vec<Trigger>
+}
+
+impl TriggerSet {
+    pub fn to_string(&self) -> String {
+        format!("?({:?})", self.triggers).to_owned()
+    }
+}
@@ -0,0 +1,14 @@
+use base::RoomId;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Warp {
+    pub to: RoomId,
+    pub active: bool
+}
+
+impl Warp {
+    pub fn to_string(&self) -> String {
+        format!("W({})", self.to.0).to_owned()
+    }
+}
+
@@ -0,0 +1,350 @@
+use std::collections::HashSet;
+use rand::{Rng, StdRng};
+
+use base::{Side, Offset, RoomId, DoorId, EntityId, sorted_offsets};
+use dungeon::Dungeon;
+use entity::Entity;
+use entity::enemy::Type as EnemyType;
+use entity::item::Item;
+use entity::trigger::{Trigger, Condition};
+use graph::Graph;
+use metrics::Metrics;
+use room::Room;
+use room::door::Lock as DoorLock;
+
+// The bare room and door layout of a dungeon, without anything placed in it
+#[derive(Debug, PartialEq, Clone)]
+pub struct Layout {
+    pub rooms: Vec<Offset>,
+    pub doors: Vec<(Offset, Offset)>
+}
+
+impl Layout {
+
+    pub fn from_dungeon(dungeon: &Dungeon) -> Layout {
+
+        let rooms = sorted_offsets(dungeon.rooms.keys().cloned());
+        let mut doors: Vec<(Offset, Offset)> = dungeon.doors.values().map(|door| {
+            door.rooms
+
+        }).collect();
+
+        doors.sort();
+
+        Layout {
+            rooms: rooms,
+            doors: doors
+        }
+
+    }
+
+    pub fn door_count(&self, offset: Offset) -> usize {
+        self.doors.iter().filter(|&&(a, b)| a == offset || b == offset).count()
+    }
+
+}
+
+// A member of an evolving population, offspring are mutated copies of their
+// parent so the seed only reproduces the placement its line started out with
+pub struct Individual {
+    pub layout: Layout,
+    pub seed: Vec<usize>,
+    pub dungeon: Dungeon,
+    pub metrics: Metrics,
+    pub fitness: f32
+}
+
+
+// Mutations ------------------------------------------------------------------
+
+pub(crate) fn mutate(dungeon: &mut Dungeon, rng: &mut StdRng) -> bool {
+
+    // Try the mutations in random order until one of them applies, all
+    // of them leave the rest of the placement as it is
+    let mut mutations = vec![0, 1, 2, 3, 4, 5];
+    rng.shuffle(&mut mutations);
+    for mutation in mutations {
+        let mutated = match mutation {
+            0 => move_lock(dungeon, rng),
+            1 => move_key_source(dungeon, rng),
+            2 => add_dead_end(dungeon, rng),
+            3 => remove_dead_end(dungeon, rng),
+            4 => add_loop(dungeon, rng),
+            _ => remove_loop(dungeon, rng)
+        };
+
+        if mutated {
+            return true;
+        }
+    }
+
+    false
+
+}
+
+fn move_lock(dungeon: &mut Dungeon, rng: &mut StdRng) -> bool {
+
+    let mut locked: Vec<DoorId> = dungeon.doors.values().filter(|door| {
+        door.lock == DoorLock::SmallKey
+
+    }).map(|door| door.id).collect();
+
+    let mut open = plain_doors(dungeon);
+    if locked.len() == 0 || open.len() == 0 {
+        return false;
+    }
+
+    locked.sort();
+    open.sort();
+
+    let from = locked[rng.gen_range(0, locked.len())];
+    let to = open[rng.gen_range(0, open.len())];
+    dungeon.doors.get_mut(&from).unwrap().lock = DoorLock::None;
+    dungeon.doors.get_mut(&to).unwrap().lock = DoorLock::SmallKey;
+    true
+
+}
+
+fn move_key_source(dungeon: &mut Dungeon, rng: &mut StdRng) -> bool {
+
+    // Only sources which nothing else depends on can be moved on their
+    // own, enemies also count towards their room's conditions
+    let (rooms, entities) = condition_references(dungeon);
+    let mut sources: Vec<(Offset, EntityId)> = Vec::new();
+    for offset in sorted_offsets(dungeon.rooms.keys().cloned()) {
+        let room = dungeon.rooms.get(&offset).unwrap();
+        for &(id, ref entity) in room.entities.iter() {
+            let depended_on = entities.contains(&id) || match *entity {
+                Entity::Enemy(_) => rooms.contains(&room.id),
+                _ => false
+            };
+
+            if is_key_source(entity) && depended_on == false {
+                sources.push((offset, id));
+            }
+        }
+    }
+
+    let targets: Vec<Offset> = dungeon.empty_rooms().into_iter().filter(|offset| {
+        dungeon.is_fixed_room(*offset) == false
+
+    }).collect();
+
+    if sources.len() == 0 || targets.len() == 0 {
+        return false;
+    }
+
+    let (from, id) = sources[rng.gen_range(0, sources.len())];
+    let to = targets[rng.gen_range(0, targets.len())];
+    let entity = {
+        let room = dungeon.rooms.get_mut(&from).unwrap();
+        let index = room.entities.iter().position(|&(e, _)| e == id).unwrap();
+        room.entities.remove(index).1
+    };
+
+    dungeon.rooms.get_mut(&to).unwrap().add_entity(id, entity);
+    true
+
+}
+
+fn add_dead_end(dungeon: &mut Dungeon, rng: &mut StdRng) -> bool {
+
+    let mut free: Vec<(Offset, Offset)> = Vec::new();
+    for offset in sorted_offsets(dungeon.rooms.keys().cloned()) {
+        if dungeon.is_fixed_room(offset) == false {
+            for side in Side::all() {
+                let next = offset + side.to_offset();
+                if dungeon.is_free_cell(next) {
+                    free.push((offset, next));
+                }
+            }
+        }
+    }
+
+    if free.len() == 0 {
+        return false;
+    }
+
+    let (from, to) = free[rng.gen_range(0, free.len())];
+    dungeon.add_connected_room(to, from);
+    Dungeon::set_room_type(dungeon.rooms.get_mut(&from).unwrap());
+    Dungeon::set_room_type(dungeon.rooms.get_mut(&to).unwrap());
+    true
+
+}
+
+fn remove_dead_end(dungeon: &mut Dungeon, rng: &mut StdRng) -> bool {
+
+    // Rooms along the top and left border can only go if others still
+    // keep the dungeon's bounds where they are
+    let plain_doors = plain_doors(dungeon);
+    let ends: Vec<Offset> = sorted_offsets(dungeon.rooms.keys().cloned()).into_iter().filter(|offset| {
+        let room = dungeon.rooms.get(offset).unwrap();
+        room.doors.len() == 1
+            && room.is_empty()
+            && plain_doors.contains(&room.doors[0].door)
+            && dungeon.rooms.keys().any(|o| o != offset && o.x == 0)
+            && dungeon.rooms.keys().any(|o| o != offset && o.y == 0)
+
+    }).collect();
+
+    if ends.len() == 0 {
+        return false;
+    }
+
+    let end = ends[rng.gen_range(0, ends.len())];
+    dungeon.remove_room(end);
+    true
+
+}
+
+fn add_loop(dungeon: &mut Dungeon, rng: &mut StdRng) -> bool {
+
+    let mut pairs: Vec<(Offset, Offset)> = Vec::new();
+    for offset in sorted_offsets(dungeon.rooms.keys().cloned()) {
+        for side in [Side::East, Side::South].iter() {
+            let next = offset + side.to_offset();
+            if dungeon.rooms.contains_key(&next)
+                && dungeon.door_between(offset, next).is_none()
+                && dungeon.is_fixed_room(offset) == false
+                && dungeon.is_fixed_room(next) == false {
+
+                pairs.push((offset, next));
+            }
+        }
+    }
+
+    if pairs.len() == 0 {
+        return false;
+    }
+
+    let (a, b) = pairs[rng.gen_range(0, pairs.len())];
+    dungeon.connect_rooms(a, b, DoorLock::None);
+    Dungeon::set_room_type(dungeon.rooms.get_mut(&a).unwrap());
+    Dungeon::set_room_type(dungeon.rooms.get_mut(&b).unwrap());
+    true
+
+}
+
+fn remove_loop(dungeon: &mut Dungeon, rng: &mut StdRng) -> bool {
+
+    // Only doors on a loop can go without splitting up the dungeon
+    let bridges = Graph::from_dungeon(dungeon).bridge_doors();
+    let mut doors: Vec<DoorId> = plain_doors(dungeon).into_iter().filter(|id| {
+        bridges.contains(id) == false
+
+    }).collect();
+
+    if doors.len() == 0 {
+        return false;
+    }
+
+    doors.sort();
+    let door = doors[rng.gen_range(0, doors.len())];
+    dungeon.remove_door(door);
+    true
+
+}
+
+fn plain_doors(dungeon: &Dungeon) -> Vec<DoorId> {
+
+    // Doors without a lock which no trigger opens or closes and which do
+    // not lead into any of the fixed rooms
+    let mut triggered: HashSet<DoorId> = HashSet::new();
+    for room in dungeon.rooms.values() {
+        for trigger in room_triggers(room) {
+            match *trigger {
+                Trigger::LockDoor(id) | Trigger::OpenDoor(id) => {
+                    triggered.insert(id);
+                },
+                _ => {}
+            }
+        }
+    }
+
+    dungeon.doors.values().filter(|door| {
+        door.lock == DoorLock::None
+            && triggered.contains(&door.id) == false
+            && dungeon.is_fixed_room(door.rooms.0) == false
+            && dungeon.is_fixed_room(door.rooms.1) == false
+
+    }).map(|door| door.id).collect()
+
+}
+
+fn room_triggers(room: &Room) -> Vec<&Trigger> {
+    let mut triggers: Vec<&Trigger> = Vec::new();
+    for &(_, ref entity) in room.entities.iter() {
+        triggers.extend(entity_triggers(entity).iter());
+    }
+    for set in room.trigger_sets.iter() {
+        triggers.extend(set.triggers.iter());
+    }
+    for d in room.doors.iter() {
+        triggers.extend(d.triggers.iter());
+    }
+    triggers
+}
+
+fn entity_triggers(entity: &Entity) -> &[Trigger] {
+    match *entity {
+        Entity::Switch(ref switch) => &switch.triggers[..],
+        Entity::Enemy(ref enemy) => &enemy.triggers[..],
+        _ => &[]
+    }
+}
+
+fn condition_references(dungeon: &Dungeon) -> (HashSet<RoomId>, HashSet<EntityId>) {
+    let mut rooms = HashSet::new();
+    let mut entities = HashSet::new();
+    for room in dungeon.rooms.values() {
+        for set in room.trigger_sets.iter() {
+            add_condition_references(&set.condition, &mut rooms, &mut entities);
+        }
+    }
+    (rooms, entities)
+}
+
+fn add_condition_references(
+    condition: &Condition, rooms: &mut HashSet<RoomId>, entities: &mut HashSet<EntityId>
+) {
+    match *condition {
+        Condition::EnemiesDefeated(room) | Condition::TorchesLit(room) => {
+            rooms.insert(room);
+        },
+        Condition::SwitchPressed(id) | Condition::ChestOpened(id) => {
+            entities.insert(id);
+        },
+        Condition::All(ref conditions) | Condition::Any(ref conditions) => {
+            for condition in conditions.iter() {
+                add_condition_references(condition, rooms, entities);
+            }
+        }
+    }
+}
+
+fn is_key_source(entity: &Entity) -> bool {
+
+    let is_key = |item: &Item| match *item {
+        Item::Key(_) => true,
+        _ => false
+    };
+
+    // Switches and enemies only count if all they do is drop a key
+    let drops_key = |triggers: &[Trigger]| triggers.len() > 0 && triggers.iter().all(|trigger| {
+        match *trigger {
+            Trigger::Item(ref item) => is_key(item),
+            Trigger::Chest(ref chest) => is_key(&chest.item),
+            _ => false
+        }
+    });
+
+    match *entity {
+        Entity::Chest(ref chest) => is_key(&chest.item),
+        Entity::Pot(ref pot) => is_key(&pot.item),
+        Entity::Switch(ref switch) => drops_key(&switch.triggers),
+        Entity::Enemy(ref enemy) => enemy.typ == EnemyType::Small && drops_key(&enemy.triggers),
+        _ => false
+    }
+
+}
@@ -0,0 +1,564 @@
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+
+use base::{Offset, DoorId, EntityId, sorted_offsets};
+use dungeon::Dungeon;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Vertex {
+    Room(Offset),
+    Door(DoorId),
+    Warp(EntityId)
+}
+
+pub struct Graph {
+    vertices: Vec<Vertex>,
+    indices: HashMap<Vertex, usize>,
+    room_count: usize,
+    // Directed edges room -> door -> room, following one way doors, and
+    // room -> warp -> room
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+    // Undirected room adjacency as (room, door or warp) vertex pairs
+    neighbours: Vec<Vec<(usize, usize)>>
+}
+
+impl Graph {
+
+    pub fn from_dungeon(dungeon: &Dungeon) -> Graph {
+
+        let rooms = sorted_offsets(dungeon.rooms.keys().cloned());
+
+        let mut doors: Vec<DoorId> = dungeon.doors.keys().cloned().collect();
+        doors.sort();
+
+        // Active warps lead from one room to another just like one way doors
+        // do, inactive ones need to be triggered during a walk through first
+        let mut warps: Vec<(EntityId, Offset, Offset)> = Vec::new();
+        for offset in rooms.iter() {
+            for &(id, warp) in dungeon.rooms.get(offset).unwrap().active_warps().iter() {
+                warps.push((id, *offset, dungeon.room(warp.to).unwrap().offset));
+            }
+        }
+
+        // Doors and warps become vertices of their own so they can dominate
+        // rooms too
+        let mut vertices: Vec<Vertex> = rooms.iter().map(|o| Vertex::Room(*o)).collect();
+        vertices.extend(doors.iter().map(|d| Vertex::Door(*d)));
+        vertices.extend(warps.iter().map(|&(id, _, _)| Vertex::Warp(id)));
+
+        let mut indices = HashMap::new();
+        for (index, vertex) in vertices.iter().enumerate() {
+            indices.insert(*vertex, index);
+        }
+
+        let mut successors = vec![Vec::new(); vertices.len()];
+        let mut predecessors = vec![Vec::new(); vertices.len()];
+        let mut neighbours = vec![Vec::new(); rooms.len()];
+        for id in doors.iter() {
+
+            let door = dungeon.door(*id).unwrap();
+            let d = indices[&Vertex::Door(*id)];
+            let a = indices[&Vertex::Room(door.rooms.0)];
+            let b = indices[&Vertex::Room(door.rooms.1)];
+
+            for &(from, to) in [(a, b), (b, a)].iter() {
+                if door.is_passable_from(vertices[from].offset()) {
+                    successors[from].push(d);
+                    predecessors[d].push(from);
+                    successors[d].push(to);
+                    predecessors[to].push(d);
+                }
+            }
+
+            neighbours[a].push((b, d));
+            neighbours[b].push((a, d));
+
+        }
+
+        for &(id, from, to) in warps.iter() {
+
+            let w = indices[&Vertex::Warp(id)];
+            let a = indices[&Vertex::Room(from)];
+            let b = indices[&Vertex::Room(to)];
+
+            successors[a].push(w);
+            predecessors[w].push(a);
+            successors[w].push(b);
+            predecessors[b].push(w);
+
+            neighbours[a].push((b, w));
+            neighbours[b].push((a, w));
+
+        }
+
+        Graph {
+            vertices: vertices,
+            indices: indices,
+            room_count: rooms.len(),
+            successors: successors,
+            predecessors: predecessors,
+            neighbours: neighbours
+        }
+
+    }
+
+    pub fn dominators(&self, root: Offset) -> Dominators {
+
+        let root = self.indices[&Vertex::Room(root)];
+
+        // Reverse post order of all vertices reachable from the root
+        let mut order = Vec::new();
+        let mut visited = vec![false; self.vertices.len()];
+        let mut stack = vec![(root, 0)];
+        visited[root] = true;
+        while let Some((vertex, next)) = stack.pop() {
+            if next < self.successors[vertex].len() {
+                stack.push((vertex, next + 1));
+                let successor = self.successors[vertex][next];
+                if visited[successor] == false {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+
+            } else {
+                order.push(vertex);
+            }
+        }
+        order.reverse();
+
+        let mut position = vec![usize::max_value(); self.vertices.len()];
+        for (index, vertex) in order.iter().enumerate() {
+            position[*vertex] = index;
+        }
+
+        // Iterate until the immediate dominators settle, see Cooper, Harvey
+        // and Kennedy's "A Simple, Fast Dominance Algorithm"
+        let mut idom: Vec<Option<usize>> = vec![None; self.vertices.len()];
+        idom[root] = Some(root);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for vertex in order.iter().skip(1) {
+
+                let mut dominator: Option<usize> = None;
+                for predecessor in self.predecessors[*vertex].iter() {
+                    if idom[*predecessor].is_some() {
+                        dominator = Some(match dominator {
+                            Some(d) => Graph::intersect(&idom, &position, d, *predecessor),
+                            None => *predecessor
+                        });
+                    }
+                }
+
+                if dominator.is_some() && idom[*vertex] != dominator {
+                    idom[*vertex] = dominator;
+                    changed = true;
+                }
+
+            }
+        }
+
+        let mut immediate = HashMap::new();
+        for vertex in order.iter().skip(1) {
+            immediate.insert(self.vertices[*vertex], self.vertices[idom[*vertex].unwrap()]);
+        }
+
+        Dominators {
+            root: self.vertices[root],
+            immediate: immediate
+        }
+
+    }
+
+    pub fn articulation_rooms(&self) -> Vec<Offset> {
+        let (rooms, _) = self.cut_vertices_and_edges();
+        rooms
+    }
+
+    pub fn bridge_doors(&self) -> Vec<DoorId> {
+        let (_, doors) = self.cut_vertices_and_edges();
+        doors
+    }
+
+    pub fn path(&self, from: Offset, to: Offset) -> Option<Vec<Offset>> {
+
+        let start = self.indices[&Vertex::Room(from)];
+        let end = self.indices[&Vertex::Room(to)];
+
+        // Breadth first search, remembering where each vertex was reached from
+        let mut previous: Vec<Option<usize>> = vec![None; self.vertices.len()];
+        let mut to_visit = VecDeque::new();
+        to_visit.push_back(start);
+        previous[start] = Some(start);
+        while let Some(vertex) = to_visit.pop_front() {
+            if previous[end].is_some() {
+                break;
+            }
+            for successor in self.successors[vertex].iter() {
+                if previous[*successor].is_none() {
+                    previous[*successor] = Some(vertex);
+                    to_visit.push_back(*successor);
+                }
+            }
+        }
+
+        if previous[end].is_none() {
+            return None;
+        }
+
+        let mut path = vec![to];
+        let mut vertex = end;
+        while vertex != start {
+            vertex = previous[vertex].unwrap();
+            if let Vertex::Room(offset) = self.vertices[vertex] {
+                path.push(offset);
+            }
+        }
+
+        path.reverse();
+        Some(path)
+
+    }
+
+    pub fn distance(&self, from: Offset, to: Offset) -> Option<usize> {
+        self.path(from, to).map(|path| path.len() - 1)
+    }
+
+
+    // Internal ---------------------------------------------------------------
+
+    fn intersect(idom: &[Option<usize>], position: &[usize], a: usize, b: usize) -> usize {
+        let (mut a, mut b) = (a, b);
+        while a != b {
+            while position[a] > position[b] {
+                a = idom[a].unwrap();
+            }
+            while position[b] > position[a] {
+                b = idom[b].unwrap();
+            }
+        }
+        a
+    }
+
+    fn cut_vertices_and_edges(&self) -> (Vec<Offset>, Vec<DoorId>) {
+
+        // Tarjan's lowpoint search over the undirected room graph
+        let mut discovered = vec![0; self.room_count];
+        let mut low = vec![0; self.room_count];
+        let mut cut_rooms = vec![false; self.room_count];
+        let mut cut_doors = Vec::new();
+        let mut time = 0;
+
+        for room in 0..self.room_count {
+            if discovered[room] == 0 {
+                self.lowpoint(
+                    room, &mut time,
+                    &mut discovered, &mut low, &mut cut_rooms, &mut cut_doors
+                );
+            }
+        }
+
+        let rooms = (0..self.room_count).filter(|r| cut_rooms[*r]).map(|r| {
+            self.vertices[r].offset()
+
+        }).collect();
+
+        // Warps can be bridges too, but only doors are reported
+        let mut doors: Vec<DoorId> = cut_doors.iter().filter_map(|d| {
+            match self.vertices[*d] {
+                Vertex::Door(id) => Some(id),
+                _ => None
+            }
+
+        }).collect();
+        doors.sort();
+
+        (rooms, doors)
+
+    }
+
+    fn lowpoint(
+        &self,
+        root: usize,
+        time: &mut usize,
+        discovered: &mut Vec<usize>,
+        low: &mut Vec<usize>,
+        cut_rooms: &mut Vec<bool>,
+        cut_doors: &mut Vec<usize>
+    ) {
+
+        // Depth first search with an explicit stack of (room, door it was
+        // entered through, next neighbour, children) since long corridors
+        // would otherwise overflow the call stack
+        *time += 1;
+        discovered[root] = *time;
+        low[root] = *time;
+
+        let mut stack: Vec<(usize, Option<usize>, usize, usize)> = vec![(root, None, 0, 0)];
+        while let Some(&(room, via, next, children)) = stack.last() {
+
+            if next < self.neighbours[room].len() {
+
+                stack.last_mut().unwrap().2 += 1;
+
+                let (other, door) = self.neighbours[room][next];
+                if discovered[other] == 0 {
+                    stack.last_mut().unwrap().3 += 1;
+                    *time += 1;
+                    discovered[other] = *time;
+                    low[other] = *time;
+                    stack.push((other, Some(door), 0, 0));
+
+                } else if via != Some(door) {
+                    low[room] = cmp::min(low[room], discovered[other]);
+                }
+
+                continue;
+
+            }
+
+            stack.pop();
+            match stack.last() {
+                Some(&(parent, parent_via, _, _)) => {
+
+                    low[parent] = cmp::min(low[parent], low[room]);
+
+                    if parent_via.is_some() && low[room] >= discovered[parent] {
+                        cut_rooms[parent] = true;
+                    }
+
+                    if low[room] > discovered[parent] {
+                        cut_doors.push(via.unwrap());
+                    }
+
+                },
+
+                // The root of the search is only a cut room if it has several
+                // independent subtrees
+                None => {
+                    if children > 1 {
+                        cut_rooms[room] = true;
+                    }
+                }
+            }
+
+        }
+
+    }
+
+}
+
+impl Vertex {
+
+    fn offset(&self) -> Offset {
+        match *self {
+            Vertex::Room(offset) => offset,
+            _ => unreachable!()
+        }
+    }
+
+}
+
+pub struct Dominators {
+    root: Vertex,
+    immediate: HashMap<Vertex, Vertex>
+}
+
+impl Dominators {
+
+    pub fn immediate(&self, vertex: Vertex) -> Option<Vertex> {
+        self.immediate.get(&vertex).cloned()
+    }
+
+    pub fn is_reachable(&self, vertex: Vertex) -> bool {
+        vertex == self.root || self.immediate.contains_key(&vertex)
+    }
+
+    pub fn dominates(&self, a: Vertex, b: Vertex) -> bool {
+        self.is_reachable(b) && self.chain(b).contains(&a)
+    }
+
+    pub fn chain(&self, vertex: Vertex) -> Vec<Vertex> {
+        let mut chain = vec![vertex];
+        while let Some(dominator) = self.immediate(chain[chain.len() - 1]) {
+            chain.push(dominator);
+        }
+        chain.reverse();
+        chain
+    }
+
+    pub fn rooms(&self, offset: Offset) -> Vec<Offset> {
+        self.chain(Vertex::Room(offset)).into_iter().filter_map(|v| {
+            match v {
+                Vertex::Room(offset) => Some(offset),
+                _ => None
+            }
+
+        }).collect()
+    }
+
+    pub fn doors(&self, offset: Offset) -> Vec<DoorId> {
+        self.chain(Vertex::Room(offset)).into_iter().filter_map(|v| {
+            match v {
+                Vertex::Door(id) => Some(id),
+                _ => None
+            }
+
+        }).collect()
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::thread;
+
+    use base::Offset;
+    use dungeon::Dungeon;
+    use entity::Entity;
+    use entity::warp::Warp;
+    use room::door::Lock as DoorLock;
+    use super::{Graph, Vertex};
+
+    fn room(x: i32, y: i32) -> Vertex {
+        Vertex::Room(Offset { x: x, y: y })
+    }
+
+    fn door(dungeon: &Dungeon, a: (i32, i32), b: (i32, i32)) -> Vertex {
+        Vertex::Door(dungeon.door_between(Offset { x: a.0, y: a.1 }, Offset { x: b.0, y: b.1 }).unwrap().id)
+    }
+
+    #[test]
+    fn doors_on_the_only_way_dominate_the_rooms_behind_them() {
+
+        let dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (2, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (2, 0), DoorLock::None)
+        ]);
+
+        let dominators = Graph::from_dungeon(&dungeon).dominators(Offset { x: 0, y: 0 });
+        assert_eq!(dominators.chain(room(2, 0)), vec![
+            room(0, 0), door(&dungeon, (0, 0), (1, 0)), room(1, 0), door(&dungeon, (1, 0), (2, 0)), room(2, 0)
+        ]);
+        assert!(dominators.dominates(room(1, 0), room(2, 0)));
+        assert!(dominators.dominates(room(2, 0), room(1, 0)) == false);
+
+    }
+
+    #[test]
+    fn warps_dominate_the_rooms_only_they_lead_to() {
+
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (5, 5)], &[
+            ((0, 0), (1, 0), DoorLock::None)
+        ]);
+
+        let to = dungeon.rooms[&Offset { x: 5, y: 5 }].id;
+        let warp = dungeon.add_entity((1, 0), Entity::Warp(Warp {
+            to: to,
+            active: true
+        }));
+
+        let dominators = Graph::from_dungeon(&dungeon).dominators(Offset { x: 0, y: 0 });
+        assert_eq!(dominators.immediate(room(5, 5)), Some(Vertex::Warp(warp)));
+        assert_eq!(dominators.immediate(Vertex::Warp(warp)), Some(room(1, 0)));
+
+    }
+
+    #[test]
+    fn inactive_warps_are_not_followed() {
+
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (5, 5)], &[
+            ((0, 0), (1, 0), DoorLock::None)
+        ]);
+
+        let to = dungeon.rooms[&Offset { x: 5, y: 5 }].id;
+        dungeon.add_entity((1, 0), Entity::Warp(Warp {
+            to: to,
+            active: false
+        }));
+
+        let graph = Graph::from_dungeon(&dungeon);
+        assert_eq!(graph.distance(Offset { x: 0, y: 0 }, Offset { x: 5, y: 5 }), None);
+        assert_eq!(graph.dominators(Offset { x: 0, y: 0 }).immediate(room(5, 5)), None);
+
+    }
+
+    #[test]
+    fn one_way_doors_only_lead_out_of_their_side() {
+
+        // E - A - B - C with a one way door from C back to E, which does not
+        // open up a second way from E to C
+        let dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (1, 1), (0, 1)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (1, 1), DoorLock::None),
+            ((1, 1), (0, 1), DoorLock::None),
+            ((0, 1), (0, 0), DoorLock::OneWay(Offset { x: 0, y: 1 }))
+        ]);
+
+        let graph = Graph::from_dungeon(&dungeon);
+        let dominators = graph.dominators(Offset { x: 0, y: 0 });
+        assert!(dominators.dominates(room(1, 1), room(0, 1)));
+        assert_eq!(graph.distance(Offset { x: 0, y: 0 }, Offset { x: 0, y: 1 }), Some(3));
+        assert_eq!(graph.distance(Offset { x: 0, y: 1 }, Offset { x: 0, y: 0 }), Some(1));
+
+        // Backwards the rooms are reachable both ways round
+        let dominators = graph.dominators(Offset { x: 0, y: 1 });
+        assert_eq!(dominators.immediate(room(1, 0)), Some(room(0, 1)));
+
+    }
+
+    #[test]
+    fn rooms_joining_two_parts_are_articulation_rooms() {
+
+        let dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (2, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (2, 0), DoorLock::None)
+        ]);
+
+        assert_eq!(Graph::from_dungeon(&dungeon).articulation_rooms(), vec![Offset { x: 1, y: 0 }]);
+
+    }
+
+    #[test]
+    fn only_doors_outside_of_loops_are_bridges() {
+
+        // A loop of four rooms with a dead end hanging off of it
+        let dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (1, 1), (0, 1), (2, 1)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (1, 1), DoorLock::None),
+            ((1, 1), (0, 1), DoorLock::None),
+            ((0, 1), (0, 0), DoorLock::None),
+            ((1, 1), (2, 1), DoorLock::None)
+        ]);
+
+        let graph = Graph::from_dungeon(&dungeon);
+        let bridge = dungeon.door_between(Offset { x: 1, y: 1 }, Offset { x: 2, y: 1 }).unwrap().id;
+        assert_eq!(graph.bridge_doors(), vec![bridge]);
+        assert_eq!(graph.articulation_rooms(), vec![Offset { x: 1, y: 1 }]);
+
+    }
+
+    #[test]
+    fn long_corridors_do_not_overflow_small_stacks() {
+
+        let rooms: Vec<(i32, i32)> = (0..20000).map(|x| (x, 0)).collect();
+        let doors: Vec<((i32, i32), (i32, i32), DoorLock)> = (1..20000).map(|x| {
+            ((x - 1, 0), (x, 0), DoorLock::None)
+
+        }).collect();
+
+        let dungeon = Dungeon::from_rooms(&rooms, &doors);
+        let bridges = thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+            Graph::from_dungeon(&dungeon).bridge_doors().len()
+
+        }).unwrap().join().unwrap();
+
+        assert_eq!(bridges, 19999);
+
+    }
+
+}
@@ -3,7 +3,15 @@ extern crate rand;
 pub mod entity;
 pub mod room;
 pub mod base;
+pub mod state;
+pub mod mission;
+pub mod graph;
+pub mod metrics;
+pub mod evolution;
+pub mod batch;
+pub mod session;
 mod dungeon;
 
 pub use dungeon::Dungeon;
+pub use state::State;
 
@@ -0,0 +1,331 @@
+use dungeon::Dungeon;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Metrics {
+    // Number of rooms in the dungeon, the other metrics leave out secret
+    // rooms and the hidden doors leading to them
+    pub rooms: usize,
+    // Number of secret rooms behind hidden doors
+    pub secret_rooms: usize,
+    // Number of rooms on the shortest path from the entrance to the exit
+    pub critical_path: usize,
+    // Rooms walked beyond the critical path by a greedy route through the
+    // dungeon, which always heads for the closest room with something left
+    // to do and only uses keys once nothing else is, every leg is as short as
+    // the keys, locks, water and crystals allow but the route as a whole is
+    // no optimal playthrough
+    pub greedy_backtracking: usize,
+    // Average number of onward doors in rooms which are not dead ends
+    pub branching_factor: f32,
+    // Rooms with a single door, not counting the entrance and exit
+    pub dead_ends: usize,
+    // Independent cycles in the room graph
+    pub loops: usize,
+    // Doors which require a small key, the boss key or an item
+    pub locked_doors: usize,
+    // Locked doors on the critical path
+    pub critical_locks: usize,
+    // Rooms walked along the same greedy route between picking up each key
+    // and using it on a door
+    pub key_distances: Vec<usize>,
+    // Share of rooms holding at least one entity
+    pub content_ratio: f32
+}
+
+impl Metrics {
+
+    pub fn average_key_distance(&self) -> f32 {
+        if self.key_distances.len() > 0 {
+            self.key_distances.iter().sum::<usize>() as f32 / self.key_distances.len() as f32
+
+        } else {
+            0.0
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        format!(
+            "Rooms: {}\nSecret rooms: {}\nCritical path: {}\nGreedy backtracking: {}\nBranching factor: {:.2}\nDead ends: {}\nLoops: {}\nLocked doors: {} ({} on the critical path)\nKey distances: {:?} (average {:.2})\nContent ratio: {:.2}",
+            self.rooms,
+            self.secret_rooms,
+            self.critical_path,
+            self.greedy_backtracking,
+            self.branching_factor,
+            self.dead_ends,
+            self.loops,
+            self.locked_doors,
+            self.critical_locks,
+            self.key_distances,
+            self.average_key_distance(),
+            self.content_ratio
+        )
+    }
+
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Error {
+    InvalidRange(&'static str),
+    TooFewRooms(usize),
+    TooManyRooms(usize),
+    NotFound
+}
+
+impl Error {
+    pub fn to_string(&self) -> String {
+        match *self {
+            Error::InvalidRange(metric) => {
+                format!("the range for {} has a minimum larger than its maximum", metric)
+            },
+            Error::TooFewRooms(rooms) => {
+                format!("dungeons need at least {} rooms but at most {} are allowed", Dungeon::MIN_ROOM_COUNT, rooms)
+            },
+            Error::TooManyRooms(rooms) => {
+                format!("dungeons can have at most {} rooms but at least {} are required", Dungeon::MAX_ROOM_COUNT, rooms)
+            },
+            Error::NotFound => {
+                "no dungeon could be generated for the targets".to_owned()
+            }
+        }
+    }
+}
+
+// Inclusive ranges which the metrics of a dungeon should lie within, unset
+// ranges are not taken into account
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Targets {
+    pub rooms: Option<(usize, usize)>,
+    pub critical_path: Option<(usize, usize)>,
+    pub greedy_backtracking: Option<(usize, usize)>,
+    pub branching_factor: Option<(f32, f32)>,
+    pub dead_ends: Option<(usize, usize)>,
+    pub loops: Option<(usize, usize)>,
+    pub locked_doors: Option<(usize, usize)>,
+    pub critical_locks: Option<(usize, usize)>,
+    pub key_distance: Option<(f32, f32)>,
+    pub content_ratio: Option<(f32, f32)>
+}
+
+impl Targets {
+
+    pub fn is_valid(&self) -> bool {
+        self.check().is_ok()
+    }
+
+    pub fn check(&self) -> Result<(), Error> {
+
+        let counts = [
+            ("rooms", self.rooms),
+            ("critical path", self.critical_path),
+            ("greedy backtracking", self.greedy_backtracking),
+            ("dead ends", self.dead_ends),
+            ("loops", self.loops),
+            ("locked doors", self.locked_doors),
+            ("critical locks", self.critical_locks)
+        ];
+
+        let ratios = [
+            ("branching factor", self.branching_factor),
+            ("key distance", self.key_distance),
+            ("content ratio", self.content_ratio)
+        ];
+
+        // Comparing this way round also rejects NaN bounds
+        for &(metric, range) in counts.iter() {
+            if range.map_or(false, |(min, max)| (min <= max) == false) {
+                return Err(Error::InvalidRange(metric));
+            }
+        }
+
+        for &(metric, range) in ratios.iter() {
+            if range.map_or(false, |(min, max)| (min <= max) == false) {
+                return Err(Error::InvalidRange(metric));
+            }
+        }
+
+        // The room range also has to overlap the sizes which can be generated
+        match self.rooms {
+            Some((_, max)) if max < Dungeon::MIN_ROOM_COUNT => Err(Error::TooFewRooms(max)),
+            Some((min, _)) if min > Dungeon::MAX_ROOM_COUNT => Err(Error::TooManyRooms(min)),
+            _ => Ok(())
+        }
+
+    }
+
+    pub fn fits(&self, metrics: &Metrics) -> bool {
+        self.score(metrics) == 0.0
+    }
+
+    pub fn score(&self, metrics: &Metrics) -> f32 {
+
+        // Sum up how far each metric lies outside of its range, relative to
+        // the missed bound so large and small metrics weigh about the same
+        let counts = [
+            (self.rooms, metrics.rooms),
+            (self.critical_path, metrics.critical_path),
+            (self.greedy_backtracking, metrics.greedy_backtracking),
+            (self.dead_ends, metrics.dead_ends),
+            (self.loops, metrics.loops),
+            (self.locked_doors, metrics.locked_doors),
+            (self.critical_locks, metrics.critical_locks)
+        ];
+
+        let ratios = [
+            (self.branching_factor, metrics.branching_factor),
+            (self.key_distance, metrics.average_key_distance()),
+            (self.content_ratio, metrics.content_ratio)
+        ];
+
+        counts.iter().filter_map(|&(range, value)| {
+            range.map(|(min, max)| Targets::deviation(min as f32, max as f32, value as f32))
+
+        }).chain(ratios.iter().filter_map(|&(range, value)| {
+            range.map(|(min, max)| Targets::deviation(min, max, value))
+
+        })).sum()
+
+    }
+
+    fn deviation(min: f32, max: f32, value: f32) -> f32 {
+        if value < min {
+            (min - value) / min.max(1.0)
+
+        } else if value > max {
+            (value - max) / max.max(1.0)
+
+        } else {
+            0.0
+        }
+    }
+
+}
+
+pub struct Candidate {
+    pub seed: Vec<usize>,
+    pub dungeon: Dungeon,
+    pub metrics: Metrics,
+    pub score: f32
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use base::Offset;
+    use dungeon::Dungeon;
+    use entity::Entity;
+    use entity::chest::Chest;
+    use entity::crystal::{Crystal, Color};
+    use entity::item::{Item, Key};
+    use room::Type as RoomType;
+    use room::door::Lock as DoorLock;
+
+    #[test]
+    fn metrics_of_a_dungeon_with_a_key_down_a_side_corridor() {
+
+        // E - A -x- X
+        //     |
+        //     B
+        //     |
+        //     C (key)
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (1, 1), (1, 2), (2, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (1, 1), DoorLock::None),
+            ((1, 1), (1, 2), DoorLock::None),
+            ((1, 0), (2, 0), DoorLock::SmallKey)
+        ]);
+
+        dungeon.add_entity((1, 2), Entity::Chest(Chest {
+            item: Item::Key(Key::Small)
+        }));
+
+        // Three rooms down to the key, two back up to the door and one more
+        // to the exit, two of those six are on the critical path
+        let metrics = dungeon.metrics();
+        assert_eq!(metrics.rooms, 5);
+        assert_eq!(metrics.critical_path, 3);
+        assert_eq!(metrics.greedy_backtracking, 4);
+        assert_eq!(metrics.key_distances, vec![2]);
+        assert_eq!(metrics.dead_ends, 1);
+        assert_eq!(metrics.loops, 0);
+        assert_eq!(metrics.locked_doors, 1);
+        assert_eq!(metrics.critical_locks, 1);
+        assert_eq!(metrics.content_ratio, 0.2);
+
+    }
+
+    #[test]
+    fn greedy_route_heads_for_the_closest_room_first() {
+
+        // C (key) - E - A - B - D (key) -x- F -x- X, the route picks up the
+        // near key on the left first, then crosses over to the far one
+        let mut dungeon = Dungeon::from_rooms(&[(1, 0), (0, 0), (2, 0), (3, 0), (4, 0), (5, 0), (6, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (2, 0), DoorLock::None),
+            ((2, 0), (3, 0), DoorLock::None),
+            ((3, 0), (4, 0), DoorLock::None),
+            ((4, 0), (5, 0), DoorLock::SmallKey),
+            ((5, 0), (6, 0), DoorLock::SmallKey)
+        ]);
+
+        for &at in [(0, 0), (4, 0)].iter() {
+            dungeon.add_entity(at, Entity::Chest(Chest {
+                item: Item::Key(Key::Small)
+            }));
+        }
+
+        // One room to the left key, four over to the right one and then the
+        // two locked doors on the way to the exit, the other way round would
+        // have walked six rooms more
+        let metrics = dungeon.metrics();
+        assert_eq!(metrics.critical_path, 6);
+        assert_eq!(metrics.greedy_backtracking, 2);
+        assert_eq!(metrics.key_distances, vec![4, 1]);
+
+    }
+
+
+    #[test]
+    fn secret_rooms_are_counted_on_their_own() {
+
+        // E - A - X with a secret room hidden behind a wall of A, which
+        // would otherwise turn A into a branch and add a dead end
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0), (1, 1), (2, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (1, 1), DoorLock::Hidden),
+            ((1, 0), (2, 0), DoorLock::None)
+        ]);
+
+        dungeon.rooms.get_mut(&Offset { x: 1, y: 1 }).unwrap().typ = RoomType::Secret;
+        dungeon.add_entity((1, 1), Entity::Chest(Chest {
+            item: Item::Currency(50)
+        }));
+
+        let metrics = dungeon.metrics();
+        assert_eq!(metrics.rooms, 3);
+        assert_eq!(metrics.secret_rooms, 1);
+        assert_eq!(metrics.dead_ends, 0);
+        assert_eq!(metrics.loops, 0);
+        assert_eq!(metrics.branching_factor, 1.0);
+        assert_eq!(metrics.content_ratio, 0.0);
+
+    }
+
+
+    #[test]
+    fn greedy_route_walks_back_to_crystals() {
+
+        // C (crystal) - E -blue- X, the way to the exit leads through C first
+        let mut dungeon = Dungeon::from_rooms(&[(1, 0), (0, 0), (2, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None),
+            ((1, 0), (2, 0), DoorLock::Barrier(Color::Blue))
+        ]);
+        dungeon.add_entity((0, 0), Entity::Crystal(Crystal));
+
+        let metrics = dungeon.metrics();
+        assert_eq!(metrics.critical_path, 2);
+        assert_eq!(metrics.greedy_backtracking, 2);
+
+    }
+
+}
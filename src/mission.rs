@@ -0,0 +1,415 @@
+use rand::{Rng, StdRng};
+
+use entity::item::Item;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Node {
+    Entrance,
+    Room,
+    Key,
+    Lock,
+    Item(Item),
+    Gate(Item),
+    BossKey,
+    Boss,
+    Exit
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Error {
+    MissingEntrance,
+    MismatchedParents(usize, usize),
+    InvalidParent(usize),
+    MissingBoss,
+    MissingExit,
+    BossNotBeforeExit,
+    TooManyRooms(usize, usize),
+    TooManyDoors(usize),
+    MissingKey(usize),
+    MissingItem(usize),
+    MissingBossKey,
+    NoLayout,
+    Unsolvable
+}
+
+impl Error {
+    pub fn to_string(&self) -> String {
+        match *self {
+            Error::MissingEntrance => {
+                "the first node of the mission must be the entrance".to_owned()
+            },
+            Error::MismatchedParents(nodes, parents) => {
+                format!("the mission has {} nodes but {} parents", nodes, parents)
+            },
+            Error::InvalidParent(node) => {
+                format!("node #{} is not connected to the entrance", node)
+            },
+            Error::MissingBoss => {
+                "the mission must contain exactly one boss".to_owned()
+            },
+            Error::MissingExit => {
+                "the mission must contain exactly one exit".to_owned()
+            },
+            Error::BossNotBeforeExit => {
+                "the exit must be the only room behind the boss".to_owned()
+            },
+            Error::TooManyRooms(rooms, budget) => {
+                format!("the mission requires {} rooms but only {} are allowed", rooms, budget)
+            },
+            Error::TooManyDoors(node) => {
+                format!("node #{} requires more than 4 doors", node)
+            },
+            Error::MissingKey(node) => {
+                format!("the lock of node #{} has no key outside of it", node)
+            },
+            Error::MissingItem(node) => {
+                format!("the gate of node #{} has no item outside of it", node)
+            },
+            Error::MissingBossKey => {
+                "the boss key cannot be reached before the boss".to_owned()
+            },
+            Error::NoLayout => {
+                "could not find a grid layout for the mission".to_owned()
+            },
+            Error::Unsolvable => {
+                "the embedded mission cannot be walked through".to_owned()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mission {
+    pub nodes: Vec<Node>,
+    pub parents: Vec<Option<usize>>
+}
+
+impl Mission {
+
+    pub fn new() -> Mission {
+        Mission {
+            nodes: vec![Node::Entrance],
+            parents: vec![None]
+        }
+    }
+
+    pub fn generate(rng: &mut StdRng, room_count: usize) -> Mission {
+
+        // Start out with the bare minimum of a dungeon
+        let mut mission = Mission::new();
+        let hub = mission.add(0, Node::Room);
+        mission.add(hub, Node::BossKey);
+        let boss = mission.add(hub, Node::Boss);
+        mission.add(boss, Node::Exit);
+
+        let mut items = vec![Item::Hookshot, Item::Bow, Item::Bombs];
+        rng.shuffle(&mut items);
+        let mut item = Some(items.remove(0));
+
+        // Then keep rewriting it until we reach the desired size
+        while mission.len() < room_count {
+            match rng.gen_range(0, 10) {
+                0 | 1 | 2 => mission.apply_lock(rng, Node::Key, Node::Lock),
+                3 if item.is_some() => {
+                    let item = item.take().unwrap();
+                    mission.apply_lock(rng, Node::Item(item.clone()), Node::Gate(item))
+                },
+                4 | 5 => mission.apply_side_quest(rng),
+                _ => mission.apply_room(rng)
+            }
+        }
+
+        mission
+
+    }
+
+    pub fn check(&self, max_rooms: usize) -> Result<(), Error> {
+
+        if self.nodes.len() != self.parents.len() {
+            return Err(Error::MismatchedParents(self.nodes.len(), self.parents.len()));
+
+        } else if self.nodes.len() == 0 || self.nodes[0] != Node::Entrance || self.parents[0].is_some() {
+            return Err(Error::MissingEntrance);
+
+        } else if self.nodes.len() > max_rooms {
+            return Err(Error::TooManyRooms(self.nodes.len(), max_rooms));
+        }
+
+        // Every node must lead back to the entrance
+        for node in 1..self.nodes.len() {
+
+            let mut current = node;
+            let mut steps = 0;
+            while let Some(parent) = self.parents[current] {
+                if parent >= self.nodes.len() || steps > self.nodes.len() {
+                    return Err(Error::InvalidParent(node));
+                }
+                current = parent;
+                steps += 1;
+            }
+
+            if current != 0 {
+                return Err(Error::InvalidParent(node));
+            }
+
+        }
+
+        // Rooms have at most four doors
+        for node in 0..self.nodes.len() {
+            let doors = self.children(node).len() + if node == 0 { 0 } else { 1 };
+            if doors > 4 {
+                return Err(Error::TooManyDoors(node));
+            }
+        }
+
+        // The boss must guard the exit
+        let bosses: Vec<usize> = (0..self.nodes.len()).filter(|i| self.nodes[*i] == Node::Boss).collect();
+        let exits: Vec<usize> = (0..self.nodes.len()).filter(|i| self.nodes[*i] == Node::Exit).collect();
+        if bosses.len() != 1 {
+            Err(Error::MissingBoss)
+
+        } else if exits.len() != 1 {
+            Err(Error::MissingExit)
+
+        } else if self.children(bosses[0]) != exits {
+            Err(Error::BossNotBeforeExit)
+
+        } else {
+            self.check_solvable()
+        }
+
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn add(&mut self, parent: usize, node: Node) -> usize {
+        self.nodes.push(node);
+        self.parents.push(Some(parent));
+        self.nodes.len() - 1
+    }
+
+    pub fn insert(&mut self, child: usize, node: Node) -> usize {
+        let parent = self.parents[child].unwrap();
+        let index = self.add(parent, node);
+        self.parents[child] = Some(index);
+        index
+    }
+
+    pub fn children(&self, index: usize) -> Vec<usize> {
+        (0..self.nodes.len()).filter(|i| self.parents[*i] == Some(index)).collect()
+    }
+
+    pub fn path(&self, index: usize) -> Vec<usize> {
+        let mut path = vec![index];
+        while let Some(parent) = self.parents[path[0]] {
+            path.insert(0, parent);
+        }
+        path
+    }
+
+
+    // Checks -----------------------------------------------------------------
+
+    fn check_solvable(&self) -> Result<(), Error> {
+
+        let children: Vec<Vec<usize>> = (0..self.nodes.len()).map(|i| self.children(i)).collect();
+
+        // Explore the mission from the entrance, stopping in front of every
+        // lock, gate and the boss until we have found what opens them.
+        //
+        // Keys are not used up here, the number of keys is only checked once
+        // the embedded dungeon is walked through
+        let mut open = vec![0];
+        let mut closed = Vec::new();
+        let mut key = false;
+        let mut items = Vec::new();
+        let mut boss_key = false;
+        loop {
+
+            while let Some(node) = open.pop() {
+
+                match self.nodes[node] {
+                    Node::Key => key = true,
+                    Node::Item(ref item) => items.push(item.clone()),
+                    Node::BossKey => boss_key = true,
+                    _ => {}
+                }
+
+                for &child in &children[node] {
+                    match self.nodes[child] {
+                        Node::Lock | Node::Gate(_) | Node::Boss => closed.push(child),
+                        _ => open.push(child)
+                    }
+                }
+
+            }
+
+            let passable = closed.iter().position(|&node| match self.nodes[node] {
+                Node::Lock => key,
+                Node::Gate(ref item) => items.contains(item),
+                _ => boss_key
+            });
+
+            if let Some(index) = passable {
+                open.push(closed.remove(index));
+
+            } else {
+                break;
+            }
+
+        }
+
+        match closed.first().map(|&node| (node, &self.nodes[node])) {
+            Some((node, &Node::Lock)) => Err(Error::MissingKey(node)),
+            Some((node, &Node::Gate(_))) => Err(Error::MissingItem(node)),
+            Some(_) => Err(Error::MissingBossKey),
+            None => Ok(())
+        }
+
+    }
+
+
+    // Rules ------------------------------------------------------------------
+
+    fn apply_lock(&mut self, rng: &mut StdRng, key: Node, lock: Node) {
+
+        // key -> lock, place the lock on any edge and its key somewhere in
+        // front of it
+        let child = match self.random_edge(rng) {
+            Some(child) => child,
+            None => return
+        };
+
+        let lock = self.insert(child, lock);
+        let path = self.path(self.parents[lock].unwrap());
+        match self.random_branch(rng, &path) {
+            Some(parent) => {
+                self.add(parent, key);
+            },
+            None => {
+                self.insert(lock, key);
+            }
+        }
+
+    }
+
+    fn apply_side_quest(&mut self, rng: &mut StdRng) {
+
+        // side quest, a detour with a key at its end, which opens up a lock
+        // further down the line
+        let candidates: Vec<usize> = (0..self.len()).collect();
+        let start = match self.random_branch(rng, &candidates) {
+            Some(start) => start,
+            None => return
+        };
+
+        let mut parent = start;
+        for _ in 0..rng.gen_range(1, 3) {
+            parent = self.add(parent, Node::Room);
+        }
+        let key = self.add(parent, Node::Key);
+
+        let behind: Vec<usize> = (1..self.len()).filter(|i| {
+            self.nodes[*i] != Node::Exit
+                && *i != start
+                && self.path(*i).contains(&start)
+                && self.path(key).contains(i) == false
+
+        }).collect();
+
+        if behind.len() > 0 {
+            let child = behind[rng.gen_range(0, behind.len())];
+            self.insert(child, Node::Lock);
+        }
+
+    }
+
+    fn apply_room(&mut self, rng: &mut StdRng) {
+
+        // Either lengthen the dungeon or add a dead end
+        if rng.gen_range(0, 3) == 0 {
+            let candidates: Vec<usize> = (0..self.len()).collect();
+            if let Some(parent) = self.random_branch(rng, &candidates) {
+                self.add(parent, Node::Room);
+                return;
+            }
+        }
+
+        if let Some(child) = self.random_edge(rng) {
+            self.insert(child, Node::Room);
+        }
+
+    }
+
+    fn nearby(&self, children: &[Vec<usize>], index: usize, distance: usize) -> Vec<usize> {
+
+        // Nodes within the given number of doors, in either direction
+        let mut nodes = vec![index];
+        let mut start = 0;
+        for _ in 0..distance {
+            let end = nodes.len();
+            for i in start..end {
+                let node = nodes[i];
+                let next = self.parents[node].into_iter().chain(children[node].iter().cloned());
+                for n in next.collect::<Vec<usize>>() {
+                    if nodes.contains(&n) == false {
+                        nodes.push(n);
+                    }
+                }
+            }
+            start = end;
+        }
+        nodes
+
+    }
+
+    fn random_edge(&self, rng: &mut StdRng) -> Option<usize> {
+
+        // Everything up to the boss room may be rewritten
+        let edges: Vec<usize> = (1..self.len()).filter(|i| {
+            self.nodes[*i] != Node::Exit
+
+        }).collect();
+
+        if edges.len() > 0 {
+            Some(edges[rng.gen_range(0, edges.len())])
+
+        } else {
+            None
+        }
+
+    }
+
+    fn random_branch(&self, rng: &mut StdRng, candidates: &[usize]) -> Option<usize> {
+
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); self.len()];
+        for (node, parent) in self.parents.iter().enumerate() {
+            if let Some(parent) = *parent {
+                children[parent].push(node);
+            }
+        }
+
+        // Every branch needs space on the grid once the mission is embedded,
+        // so a new one may neither start in a room which already branches off
+        // nor next to another branch, otherwise larger missions no longer fit
+        let branches: Vec<usize> = candidates.iter().cloned().filter(|i| {
+            self.nodes[*i] != Node::Boss
+                && self.nodes[*i] != Node::Exit
+                && children[*i].len() < 2
+                && self.nearby(&children, *i, 2).iter().all(|n| *n == *i || children[*n].len() < 2)
+
+        }).collect();
+
+        if branches.len() > 0 {
+            Some(branches[rng.gen_range(0, branches.len())])
+
+        } else {
+            None
+        }
+
+    }
+
+}
+
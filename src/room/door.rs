@@ -1,11 +1,18 @@
-use base::{Side, Offset};
+use base::{Side, Offset, DoorId};
+use entity::crystal::Color;
+use entity::item::Item;
 use entity::trigger::Trigger;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Lock {
     BossKey,
     SmallKey,
     Trigger,
+    Item(Item),
+    Barrier(Color),
+    Water(usize),
+    OneWay(Offset),
+    Hidden,
     None
 }
 
@@ -16,16 +23,60 @@ impl Lock {
             Lock::BossKey => 'B',
             Lock::SmallKey => 'S',
             Lock::Trigger => 'T',
+            Lock::Item(Item::Hookshot) => 'H',
+            Lock::Item(Item::Bow) => 'A',
+            Lock::Item(Item::Bombs) => 'X',
+            Lock::Item(_) => 'I',
+            Lock::Barrier(Color::Red) => 'r',
+            Lock::Barrier(Color::Blue) => 'b',
+            Lock::Water(0) => 'w',
+            Lock::Water(_) => 'W',
+            Lock::OneWay(_) => 'O',
+            Lock::Hidden => '#',
             Lock::None => ' '
         }
     }
 
+    pub fn is_state_dependent(&self) -> bool {
+        match *self {
+            Lock::Barrier(_) | Lock::Water(_) => true,
+            _ => false
+        }
+    }
+
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Door {
+    pub id: DoorId,
+    pub rooms: (Offset, Offset),
+    pub lock: Lock
+}
+
+impl Door {
+
+    pub fn other(&self, offset: Offset) -> Offset {
+        if self.rooms.0 == offset {
+            self.rooms.1
+
+        } else {
+            self.rooms.0
+        }
+    }
+
+    pub fn is_passable_from(&self, offset: Offset) -> bool {
+        match self.lock {
+            Lock::OneWay(from) => from == offset,
+            _ => true
+        }
+    }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub door: DoorId,
     pub side: Side,
-    pub lock: Lock,
     pub to: Offset,
     pub triggers: Vec<Trigger>
 }
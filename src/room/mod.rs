@@ -1,13 +1,20 @@
-use base::{Side, Offset};
+use base::{Side, Offset, RoomId, DoorId, EntityId};
+use entity::Entity;
 use entity::chest::Chest;
+use entity::crystal::Crystal;
 use entity::enemy::Enemy;
+use entity::pot::Pot;
 use entity::switch::Switch;
+use entity::torch::Torch;
+use entity::trap::Trap;
+use entity::trigger::TriggerSet;
+use entity::warp::Warp;
 
 pub mod door;
 pub mod path;
 pub use self::path::Path;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Type {
     None,
     Entrance,
@@ -16,6 +23,7 @@ pub enum Type {
     Intersection,
     Crossing,
     End,
+    Secret,
     Invalid
 }
 
@@ -29,53 +37,137 @@ impl Type {
             Type::Intersection => "Intersection",
             Type::Crossing => "Crossway",
             Type::End => "End",
+            Type::Secret => "Secret",
             Type::Invalid => "Invalid"
 
         }.to_owned()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Room {
+    pub id: RoomId,
     pub offset: Offset,
-    pub doors: Vec<door::Door>,
+    pub doors: Vec<door::Connection>,
     pub typ: Type,
-    pub chest: Option<Chest>,
-    pub enemy: Option<Enemy>,
-    pub switch: Option<Switch>
+    pub dark: bool,
+    pub entities: Vec<(EntityId, Entity)>,
+    pub trigger_sets: Vec<TriggerSet>
 }
 
 impl Room {
 
-    pub fn new(x: i32, y: i32) -> Room {
+    pub fn new(id: RoomId, x: i32, y: i32) -> Room {
         Room {
+            id: id,
             offset: Offset {
                 x: x,
                 y: y
             },
             doors: Vec::new(),
             typ: Type::Invalid,
-            chest: None,
-            enemy: None,
-            switch: None
+            dark: false,
+            entities: Vec::new(),
+            trigger_sets: Vec::new()
         }
     }
 
-    pub fn get_door_to_offset_mut(&mut self, to: &Offset) -> Option<&mut door::Door> {
+    pub fn connection_to(&self, to: &Offset) -> Option<&door::Connection> {
+        self.doors.iter().filter(|d| d.to == *to).next()
+    }
 
-        let side = Side::from_offsets(&self.offset, to);
+    pub fn connection_to_mut(&mut self, to: &Offset) -> Option<&mut door::Connection> {
+        self.doors.iter_mut().filter(|d| d.to == *to).next()
+    }
 
-        self.doors.iter_mut().filter(|d| {
-            d.side == side
+    pub fn entity(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.iter().filter(|&&(i, _)| i == id).map(|&(_, ref e)| e).next()
+    }
 
-        }).next()
+    pub fn add_entity(&mut self, id: EntityId, entity: Entity) {
+        self.entities.push((id, entity));
+    }
 
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty() && self.trigger_sets.is_empty()
     }
 
-    pub fn add_door_to(&mut self, other: &Room) {
-        self.doors.push(door::Door {
+    pub fn chests(&self) -> Vec<(EntityId, &Chest)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Chest(ref chest) => Some((id, chest)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn crystals(&self) -> Vec<(EntityId, &Crystal)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Crystal(ref crystal) => Some((id, crystal)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn enemies(&self) -> Vec<(EntityId, &Enemy)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Enemy(ref enemy) => Some((id, enemy)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn pots(&self) -> Vec<(EntityId, &Pot)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Pot(ref pot) => Some((id, pot)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn switches(&self) -> Vec<(EntityId, &Switch)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Switch(ref switch) => Some((id, switch)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn torches(&self) -> Vec<(EntityId, &Torch)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Torch(ref torch) => Some((id, torch)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn traps(&self) -> Vec<(EntityId, &Trap)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Trap(ref trap) => Some((id, trap)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn warps(&self) -> Vec<(EntityId, &Warp)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Warp(ref warp) => Some((id, warp)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn active_warps(&self) -> Vec<(EntityId, &Warp)> {
+        self.entities.iter().filter_map(|&(id, ref e)| match *e {
+            Entity::Warp(ref warp) if warp.active => Some((id, warp)),
+            _ => None
+
+        }).collect()
+    }
+
+    pub fn connect(&mut self, id: DoorId, other: &Room) {
+        self.doors.push(door::Connection {
+            door: id,
             side: Side::from_offsets(&self.offset, &other.offset),
-            lock: door::Lock::None,
             to: other.offset,
             triggers: Vec::new()
         })
@@ -83,3 +175,49 @@ impl Room {
 
 }
 
+#[cfg(test)]
+mod tests {
+
+    use base::{RoomId, EntityId};
+    use entity::Entity;
+    use entity::chest::Chest;
+    use entity::item::Item;
+    use entity::pot::Pot;
+    use entity::torch::Torch;
+    use super::Room;
+
+    #[test]
+    fn rooms_hold_any_number_of_entities() {
+
+        let mut room = Room::new(RoomId(0), 0, 0);
+        assert!(room.is_empty());
+
+        room.add_entity(EntityId(3), Entity::Chest(Chest {
+            item: Item::Bow
+        }));
+        room.add_entity(EntityId(1), Entity::Torch(Torch {
+            lit: false
+        }));
+        room.add_entity(EntityId(2), Entity::Chest(Chest {
+            item: Item::Currency(50)
+        }));
+
+        // Entities keep the order they were added in and can be found by id
+        assert!(room.is_empty() == false);
+        assert_eq!(room.chests(), vec![
+            (EntityId(3), &Chest { item: Item::Bow }),
+            (EntityId(2), &Chest { item: Item::Currency(50) })
+        ]);
+        assert_eq!(room.torches().len(), 1);
+        assert!(room.pots().is_empty());
+        assert_eq!(room.entity(EntityId(1)), Some(&Entity::Torch(Torch { lit: false })));
+        assert_eq!(room.entity(EntityId(4)), None);
+
+        room.add_entity(EntityId(4), Entity::Pot(Pot {
+            item: Item::None
+        }));
+        assert_eq!(room.entities.len(), 4);
+
+    }
+
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use dungeon::Dungeon;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Phase {
+    Layout,
+    SpecialRooms,
+    Locks,
+    Keys,
+    // Warps, barriers, water levels, dark rooms, shortcuts, secrets and traps
+    Features,
+    Validation
+}
+
+// How dangerous the dungeon should be for the player, easier dungeons contain
+// fewer and weaker traps
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Error {
+    Cancelled,
+    OutOfTime,
+    Failed(usize)
+}
+
+impl Error {
+    pub fn to_string(&self) -> String {
+        match *self {
+            Error::Cancelled => {
+                "generation was cancelled".to_owned()
+            },
+            Error::OutOfTime => {
+                "generation ran out of time".to_owned()
+            },
+            Error::Failed(tries) => {
+                format!("failed to generate a dungeon in {} tries", tries)
+            }
+        }
+    }
+}
+
+// Can be cloned and handed to another thread in order to stop a running
+// generation
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>
+}
+
+impl CancelToken {
+
+    pub fn new() -> CancelToken {
+        CancelToken {
+            cancelled: Arc::new(AtomicBool::new(false))
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+}
+
+// Generates a dungeon like `Dungeon::from_seed`, but reports the phase and
+// try it is currently in and can be stopped early, either through the cancel
+// token or once the optional time budget is used up
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub seed: Vec<usize>,
+    pub room_count: usize,
+    pub max_tries: usize,
+    pub difficulty: Difficulty,
+    pub budget: Option<Duration>,
+    pub cancel: CancelToken
+}
+
+impl Session {
+
+    pub fn new(seed: &[usize], room_count: usize, max_tries: usize) -> Session {
+        Session {
+            seed: seed.to_vec(),
+            room_count: room_count,
+            max_tries: max_tries,
+            difficulty: Difficulty::Normal,
+            budget: None,
+            cancel: CancelToken::new()
+        }
+    }
+
+    pub fn run<F>(&self, progress: F) -> Result<Dungeon, Error> where F : FnMut(Phase, usize) {
+        Dungeon::from_session(self, progress)
+    }
+
+}
+
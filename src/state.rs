@@ -0,0 +1,827 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use base::{Offset, RoomId, DoorId, EntityId};
+use dungeon::Dungeon;
+use room::Type as RoomType;
+use room::door::Lock as DoorLock;
+use entity::Entity;
+use entity::crystal::Color;
+use entity::item::{Item, Key};
+use entity::trap::{Trap, Type as TrapType};
+use entity::warp::Warp;
+use entity::trigger::{Trigger, Condition};
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub struct Environment {
+    pub crystal: Color,
+    pub water_level: usize
+}
+
+// Changes to the state which affect how the dungeon can be walked through
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Change {
+    Opened(DoorId),
+    Locked(DoorId),
+    Warp(EntityId),
+    Chest(Offset),
+    Environment
+}
+
+// Trigger sets by the rooms and entities which their conditions depend on
+struct TriggerIndex {
+    rooms: HashMap<RoomId, Vec<(Offset, usize)>>,
+    entities: HashMap<EntityId, Vec<(Offset, usize)>>
+}
+
+impl TriggerIndex {
+
+    fn add(&mut self, set: (Offset, usize), condition: &Condition) {
+        match *condition {
+            Condition::EnemiesDefeated(room) | Condition::TorchesLit(room) => {
+                self.rooms.entry(room).or_insert_with(Vec::new).push(set);
+            },
+            Condition::SwitchPressed(id) | Condition::ChestOpened(id) => {
+                self.entities.entry(id).or_insert_with(Vec::new).push(set);
+            },
+            Condition::All(ref conditions) | Condition::Any(ref conditions) => {
+                for condition in conditions.iter() {
+                    self.add(set, condition);
+                }
+            }
+        }
+    }
+
+}
+
+pub struct State {
+    pub small_keys: usize,
+    pub boss_key: bool,
+    pub currency: usize,
+    pub damage_taken: usize,
+    pub environment: Environment,
+    pub items: Vec<Item>,
+    defeated_enemies: HashSet<EntityId>,
+    pressed_switches: HashSet<EntityId>,
+    opened_chests: HashSet<EntityId>,
+    broken_pots: HashSet<EntityId>,
+    spawned_chests: HashMap<Offset, Vec<Item>>,
+    lit_torches: HashSet<EntityId>,
+    collapsed_floors: HashSet<EntityId>,
+    fired_sets: HashSet<(Offset, usize)>,
+    active_warps: HashSet<EntityId>,
+    unlocked_doors: HashSet<DoorId>,
+    opened_doors: HashSet<DoorId>,
+    locked_doors: HashSet<DoorId>,
+    trigger_index: Option<TriggerIndex>,
+    changes: Vec<Change>
+}
+
+impl State {
+
+    pub fn new() -> State {
+        State {
+            small_keys: 0,
+            boss_key: false,
+            currency: 0,
+            damage_taken: 0,
+            environment: Environment {
+                crystal: Color::Red,
+                water_level: 0
+            },
+            items: Vec::new(),
+            defeated_enemies: HashSet::new(),
+            pressed_switches: HashSet::new(),
+            opened_chests: HashSet::new(),
+            broken_pots: HashSet::new(),
+            spawned_chests: HashMap::new(),
+            lit_torches: HashSet::new(),
+            collapsed_floors: HashSet::new(),
+            fired_sets: HashSet::new(),
+            active_warps: HashSet::new(),
+            unlocked_doors: HashSet::new(),
+            opened_doors: HashSet::new(),
+            locked_doors: HashSet::new(),
+            trigger_index: None,
+            changes: Vec::new()
+        }
+    }
+
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        mem::replace(&mut self.changes, Vec::new())
+    }
+
+
+    // Room Events ------------------------------------------------------------
+
+    pub fn enter_room(&mut self, dungeon: &Dungeon, from: Offset, to: Offset) {
+
+        // Fire the triggers of the door we just came through, e.g. the boss
+        // room locking its entrance behind us
+        let room = dungeon.rooms.get(&to).unwrap();
+        if let Some(connection) = room.connection_to(&from) {
+
+            // One-way doors stay open once they were passed from their far side
+            if let DoorLock::OneWay(_) = dungeon.door(connection.door).unwrap().lock {
+                if self.unlocked_doors.insert(connection.door) {
+                    self.changes.push(Change::Opened(connection.door));
+                }
+            }
+
+            self.fire(to, &connection.triggers);
+
+        }
+
+    }
+
+    pub fn interact(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+        let room = dungeon.rooms.get(&offset).unwrap();
+        match room.entity(id) {
+            Some(&Entity::Torch(_)) => self.light_torch(dungeon, offset, id),
+            _ if self.is_room_lit(dungeon, offset) == false => false,
+            Some(&Entity::Enemy(_)) => self.defeat_enemy(dungeon, offset, id),
+            Some(&Entity::Switch(_)) => self.press_switch(dungeon, offset, id),
+            Some(&Entity::Chest(_)) => self.open_chest(dungeon, offset, id),
+            Some(&Entity::Crystal(_)) => self.hit_crystal(dungeon, offset, id),
+            Some(&Entity::Pot(_)) => self.break_pot(dungeon, offset, id),
+            _ => false
+        }
+    }
+
+    pub fn can_interact(&self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+        let room = dungeon.rooms.get(&offset).unwrap();
+        match room.entity(id) {
+            Some(&Entity::Torch(ref torch)) => torch.lit == false && self.lit_torches.contains(&id) == false,
+            _ if self.is_room_lit(dungeon, offset) == false => false,
+            Some(&Entity::Enemy(ref enemy)) => {
+                (enemy.weakness == Item::None || self.items.contains(&enemy.weakness))
+                    && self.defeated_enemies.contains(&id) == false
+            },
+            Some(&Entity::Switch(ref switch)) => {
+                switch.is_repeatable() || self.pressed_switches.contains(&id) == false
+            },
+            Some(&Entity::Chest(_)) => self.opened_chests.contains(&id) == false,
+            Some(&Entity::Crystal(_)) => true,
+            Some(&Entity::Pot(_)) => self.broken_pots.contains(&id) == false,
+            _ => false
+        }
+    }
+
+    pub fn defeat_enemy(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if let Some(&Entity::Enemy(ref enemy)) = room.entity(id) {
+
+            // Some enemies can only be defeated with a specific item
+            if enemy.weakness != Item::None && self.items.contains(&enemy.weakness) == false {
+                return false;
+            }
+
+            if self.defeated_enemies.insert(id) {
+                self.fire(offset, &enemy.triggers);
+                self.check_trigger_sets(dungeon, offset, id);
+                return true;
+            }
+
+        }
+
+        false
+
+    }
+
+    pub fn press_switch(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if let Some(&Entity::Switch(ref switch)) = room.entity(id) {
+
+            // Water level switches can be pressed over and over again
+            let pressed = self.pressed_switches.insert(id);
+            if pressed || switch.is_repeatable() {
+                let environment = self.environment;
+                self.fire(offset, &switch.triggers);
+                self.check_trigger_sets(dungeon, offset, id);
+                return pressed || self.environment != environment;
+            }
+
+        }
+
+        false
+
+    }
+
+    pub fn light_torch(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if let Some(&Entity::Torch(ref torch)) = room.entity(id) {
+            if torch.lit == false && self.lit_torches.insert(id) {
+                self.check_trigger_sets(dungeon, offset, id);
+                return true;
+            }
+        }
+
+        false
+
+    }
+
+    pub fn open_chest(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if let Some(&Entity::Chest(ref chest)) = room.entity(id) {
+            if self.opened_chests.insert(id) {
+                self.collect(&chest.item);
+                self.check_trigger_sets(dungeon, offset, id);
+                return true;
+            }
+        }
+
+        false
+
+    }
+
+    pub fn break_pot(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if let Some(&Entity::Pot(ref pot)) = room.entity(id) {
+            if self.broken_pots.insert(id) {
+                self.collect(&pot.item);
+                return true;
+            }
+        }
+
+        false
+
+    }
+
+    pub fn hit_crystal(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+
+        // Crystals can be hit any number of times, each hit toggles the
+        // colour of all barriers in the dungeon
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if let Some(&Entity::Crystal(_)) = room.entity(id) {
+            self.environment.crystal = self.environment.crystal.toggled();
+            self.changes.push(Change::Environment);
+            true
+
+        } else {
+            false
+        }
+
+    }
+
+    pub fn open_spawned_chest(&mut self, dungeon: &Dungeon, offset: Offset) -> bool {
+
+        if self.is_room_lit(dungeon, offset) == false {
+            return false;
+        }
+
+        // Chests spawned by triggers can only be opened after they appeared
+        let item = match self.spawned_chests.get_mut(&offset) {
+            Some(items) => items.pop(),
+            None => None
+        };
+
+        match item {
+            Some(item) => {
+                self.collect(&item);
+                true
+            },
+            None => false
+        }
+
+    }
+
+    pub fn spawned_chests(&self, offset: Offset) -> usize {
+        self.spawned_chests.get(&offset).map_or(0, |c| c.len())
+    }
+
+
+    pub fn is_room_lit(&self, dungeon: &Dungeon, offset: Offset) -> bool {
+
+        // Dark rooms can only be used with a lantern or once all of their
+        // torches are burning
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if room.dark == false || self.items.contains(&Item::Lantern) {
+            true
+
+        } else {
+            room.torches().len() > 0 && self.is_met(dungeon, &Condition::TorchesLit(room.id))
+        }
+
+    }
+
+
+    // Traps ------------------------------------------------------------------
+
+    pub fn active_traps<'a>(&self, dungeon: &'a Dungeon, offset: Offset) -> Vec<(EntityId, &'a Trap)> {
+        let room = dungeon.rooms.get(&offset).unwrap();
+        room.traps().into_iter().filter(|&(id, _)| {
+            self.collapsed_floors.contains(&id) == false
+
+        }).collect()
+    }
+
+    pub fn trigger_trap(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) -> bool {
+
+        let room = dungeon.rooms.get(&offset).unwrap();
+        if let Some(&Entity::Trap(ref trap)) = room.entity(id) {
+
+            // Falling floors only collapse once
+            if self.collapsed_floors.contains(&id) {
+                return false;
+
+            } else if trap.typ == TrapType::FallingFloor {
+                self.collapsed_floors.insert(id);
+            }
+
+            self.damage_taken += trap.damage;
+            true
+
+        } else {
+            false
+        }
+
+    }
+
+
+    // Doors ------------------------------------------------------------------
+
+    pub fn is_door_open(&self, dungeon: &Dungeon, from: Offset, id: DoorId) -> bool {
+        self.is_door_open_in(dungeon, from, id, self.environment)
+    }
+
+    pub fn is_door_open_in(
+        &self, dungeon: &Dungeon, from: Offset, id: DoorId, environment: Environment
+
+    ) -> bool {
+
+        match dungeon.door(id) {
+            Some(door) => {
+                if self.locked_doors.contains(&id) {
+                    false
+
+                } else {
+                    match door.lock {
+                        DoorLock::SmallKey | DoorLock::BossKey | DoorLock::Item(_) => {
+                            self.unlocked_doors.contains(&id)
+                        },
+                        DoorLock::Trigger => {
+                            self.opened_doors.contains(&id)
+                        },
+                        DoorLock::OneWay(_) => {
+                            door.is_passable_from(from) || self.unlocked_doors.contains(&id)
+                        },
+                        DoorLock::Barrier(color) => {
+                            color == environment.crystal
+                        },
+                        DoorLock::Water(level) => {
+                            level == environment.water_level
+                        },
+                        DoorLock::Hidden => {
+                            self.unlocked_doors.contains(&id)
+                        },
+                        DoorLock::None => true
+                    }
+                }
+            },
+            None => false
+        }
+
+    }
+
+    pub fn is_door_unlocked(&self, id: DoorId) -> bool {
+        self.unlocked_doors.contains(&id)
+    }
+
+    pub fn can_unlock_door(&self, dungeon: &Dungeon, id: DoorId) -> bool {
+
+        let lock = match dungeon.door(id) {
+            Some(door) => &door.lock,
+            None => return false
+        };
+
+        if self.unlocked_doors.contains(&id) {
+            return false;
+        }
+
+        match *lock {
+            DoorLock::SmallKey => self.small_keys > 0,
+            DoorLock::BossKey => self.boss_key,
+            DoorLock::Item(ref item) => self.items.contains(item),
+            _ => false
+        }
+
+    }
+
+    pub fn unlock_door(&mut self, dungeon: &Dungeon, id: DoorId) -> bool {
+
+        if self.can_unlock_door(dungeon, id) == false {
+            return false;
+        }
+
+        if dungeon.door(id).unwrap().lock == DoorLock::SmallKey {
+            self.small_keys -= 1;
+        }
+
+        // Doors are shared between both rooms, so they stay open from
+        // either side
+        self.unlocked_doors.insert(id);
+        self.changes.push(Change::Opened(id));
+        true
+
+    }
+
+
+    pub fn reveal_door(&mut self, dungeon: &Dungeon, id: DoorId) -> bool {
+        match dungeon.door(id) {
+            Some(door) if door.lock == DoorLock::Hidden => {
+                let revealed = self.unlocked_doors.insert(id);
+                if revealed {
+                    self.changes.push(Change::Opened(id));
+                }
+                revealed
+            },
+            _ => false
+        }
+    }
+
+
+    // Map --------------------------------------------------------------------
+
+    pub fn is_room_mapped(&self, dungeon: &Dungeon, offset: Offset) -> bool {
+
+        if self.items.contains(&Item::Map) == false {
+            return false;
+        }
+
+        // The map does not show secret rooms until their door was found
+        match dungeon.rooms.get(&offset) {
+            Some(room) if room.typ == RoomType::Secret => {
+                room.doors.iter().any(|d| self.unlocked_doors.contains(&d.door))
+            },
+            Some(_) => true,
+            None => false
+        }
+
+    }
+
+
+    // Warps ------------------------------------------------------------------
+
+    pub fn is_warp_active(&self, id: EntityId, warp: &Warp) -> bool {
+        warp.active || self.active_warps.contains(&id)
+    }
+
+
+    // Conditions -------------------------------------------------------------
+
+    pub fn is_met(&self, dungeon: &Dungeon, condition: &Condition) -> bool {
+        match *condition {
+            Condition::EnemiesDefeated(room) => {
+                dungeon.room(room).unwrap().enemies().iter().all(|&(id, _)| {
+                    self.defeated_enemies.contains(&id)
+                })
+            },
+            Condition::SwitchPressed(id) => {
+                self.pressed_switches.contains(&id)
+            },
+            Condition::TorchesLit(room) => {
+                dungeon.room(room).unwrap().torches().iter().all(|&(id, torch)| {
+                    torch.lit || self.lit_torches.contains(&id)
+                })
+            },
+            Condition::ChestOpened(id) => {
+                self.opened_chests.contains(&id)
+            },
+            Condition::All(ref conditions) => {
+                conditions.iter().all(|c| self.is_met(dungeon, c))
+            },
+            Condition::Any(ref conditions) => {
+                conditions.iter().any(|c| self.is_met(dungeon, c))
+            }
+        }
+    }
+
+
+    // Internal ---------------------------------------------------------------
+
+    fn check_trigger_sets(&mut self, dungeon: &Dungeon, offset: Offset, id: EntityId) {
+
+        // Conditions can be met from the start, so the first check covers all
+        // sets, afterwards only the ones depending on the interacted entity
+        // or its room can change
+        let sets: Vec<(Offset, usize)> = match self.trigger_index {
+            Some(ref index) => {
+                let room = dungeon.rooms.get(&offset).unwrap().id;
+                index.rooms.get(&room).into_iter().chain(index.entities.get(&id)).flat_map(|sets| {
+                    sets.iter().cloned()
+
+                }).collect()
+            },
+            None => {
+                let mut index = TriggerIndex {
+                    rooms: HashMap::new(),
+                    entities: HashMap::new()
+                };
+
+                let mut sets = Vec::new();
+                for (offset, room) in dungeon.rooms.iter() {
+                    for (i, set) in room.trigger_sets.iter().enumerate() {
+                        index.add((*offset, i), &set.condition);
+                        sets.push((*offset, i));
+                    }
+                }
+
+                self.trigger_index = Some(index);
+                sets
+            }
+        };
+
+        for (offset, index) in sets.into_iter() {
+            let set = &dungeon.rooms.get(&offset).unwrap().trigger_sets[index];
+            if self.fired_sets.contains(&(offset, index)) == false
+                && self.is_met(dungeon, &set.condition) {
+
+                self.fired_sets.insert((offset, index));
+                self.fire(offset, &set.triggers);
+
+            }
+        }
+
+    }
+
+    fn fire(&mut self, offset: Offset, triggers: &[Trigger]) {
+        for trigger in triggers.iter() {
+            match *trigger {
+                // Doors which were opened by a trigger stay open, otherwise
+                // re-entering a cleared miniboss room would lock it for good
+                Trigger::LockDoor(id) => {
+                    if self.opened_doors.contains(&id) == false {
+                        self.locked_doors.insert(id);
+                        self.changes.push(Change::Locked(id));
+                    }
+                },
+                Trigger::OpenDoor(id) => {
+                    self.locked_doors.remove(&id);
+                    self.opened_doors.insert(id);
+                    self.changes.push(Change::Opened(id));
+                },
+                Trigger::Warp(id) => {
+                    self.active_warps.insert(id);
+                    self.changes.push(Change::Warp(id));
+                },
+                Trigger::Item(ref item) => {
+                    self.collect(item);
+                },
+                Trigger::SetWaterLevel(level) => {
+                    if self.environment.water_level != level {
+                        self.environment.water_level = level;
+                        self.changes.push(Change::Environment);
+                    }
+                },
+                Trigger::Chest(ref chest) => {
+                    self.spawned_chests.entry(offset).or_insert_with(Vec::new).push(
+                        chest.item.clone()
+                    );
+                    self.changes.push(Change::Chest(offset));
+                }
+            }
+        }
+    }
+
+    fn collect(&mut self, item: &Item) {
+        match *item {
+            Item::Key(Key::Small) => {
+                self.small_keys += 1;
+            },
+            Item::Key(Key::Boss) => {
+                self.boss_key = true;
+            },
+            Item::Currency(amount) => {
+                self.currency += amount;
+            },
+            Item::None | Item::Key(Key::None) => {},
+            ref item => {
+                self.items.push(item.clone());
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use base::Offset;
+    use dungeon::Dungeon;
+    use entity::Entity;
+    use entity::chest::Chest;
+    use entity::enemy::{Enemy, Type as EnemyType};
+    use entity::item::{Item, Key};
+    use entity::switch::Switch;
+    use entity::torch::Torch;
+    use entity::trigger::{Trigger, TriggerSet, Condition};
+    use room::door::Lock as DoorLock;
+    use super::{State, Change};
+
+    fn offset(x: i32, y: i32) -> Offset {
+        Offset { x: x, y: y }
+    }
+
+    fn enemy() -> Entity {
+        Entity::Enemy(Enemy {
+            typ: EnemyType::Small,
+            weakness: Item::None,
+            triggers: Vec::new()
+        })
+    }
+
+    fn two_rooms() -> Dungeon {
+        Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+            ((0, 0), (1, 0), DoorLock::None)
+        ])
+    }
+
+    #[test]
+    fn conditions_are_met_once_their_entities_were_used() {
+
+        let mut dungeon = two_rooms();
+        let room = dungeon.rooms[&offset(0, 0)].id;
+        let first = dungeon.add_entity((0, 0), enemy());
+        let second = dungeon.add_entity((0, 0), enemy());
+        let switch = dungeon.add_entity((0, 0), Entity::Switch(Switch {
+            triggers: Vec::new()
+        }));
+        let chest = dungeon.add_entity((0, 0), Entity::Chest(Chest {
+            item: Item::Currency(50)
+        }));
+        dungeon.add_entity((1, 0), Entity::Torch(Torch {
+            lit: true
+        }));
+        let torch = dungeon.add_entity((1, 0), Entity::Torch(Torch {
+            lit: false
+        }));
+
+        let mut state = State::new();
+        let enemies = Condition::EnemiesDefeated(room);
+        let torches = Condition::TorchesLit(dungeon.rooms[&offset(1, 0)].id);
+        assert!(state.is_met(&dungeon, &enemies) == false);
+        assert!(state.is_met(&dungeon, &torches) == false);
+        assert!(state.is_met(&dungeon, &Condition::SwitchPressed(switch)) == false);
+        assert!(state.is_met(&dungeon, &Condition::ChestOpened(chest)) == false);
+
+        // All enemies in the room have to be defeated, torches which were
+        // lit from the start count as well
+        state.interact(&dungeon, offset(0, 0), first);
+        assert!(state.is_met(&dungeon, &enemies) == false);
+        state.interact(&dungeon, offset(0, 0), second);
+        assert!(state.is_met(&dungeon, &enemies));
+
+        state.interact(&dungeon, offset(1, 0), torch);
+        assert!(state.is_met(&dungeon, &torches));
+
+        state.interact(&dungeon, offset(0, 0), switch);
+        assert!(state.is_met(&dungeon, &Condition::SwitchPressed(switch)));
+        assert!(state.is_met(&dungeon, &Condition::ChestOpened(chest)) == false);
+
+        state.interact(&dungeon, offset(0, 0), chest);
+        assert!(state.is_met(&dungeon, &Condition::ChestOpened(chest)));
+
+    }
+
+    #[test]
+    fn compound_conditions_combine_their_parts() {
+
+        let mut dungeon = two_rooms();
+        let first = dungeon.add_entity((0, 0), Entity::Switch(Switch {
+            triggers: Vec::new()
+        }));
+        let second = dungeon.add_entity((0, 0), Entity::Switch(Switch {
+            triggers: Vec::new()
+        }));
+
+        let all = Condition::All(vec![Condition::SwitchPressed(first), Condition::SwitchPressed(second)]);
+        let any = Condition::Any(vec![Condition::SwitchPressed(first), Condition::SwitchPressed(second)]);
+        let nested = Condition::All(vec![any.clone(), Condition::Any(vec![all.clone()])]);
+
+        let mut state = State::new();
+        assert!(state.is_met(&dungeon, &any) == false);
+
+        state.interact(&dungeon, offset(0, 0), second);
+        assert!(state.is_met(&dungeon, &all) == false);
+        assert!(state.is_met(&dungeon, &any));
+        assert!(state.is_met(&dungeon, &nested) == false);
+
+        state.interact(&dungeon, offset(0, 0), first);
+        assert!(state.is_met(&dungeon, &all));
+        assert!(state.is_met(&dungeon, &nested));
+
+    }
+
+    #[test]
+    fn trigger_sets_fire_once_when_their_condition_is_met() {
+
+        let mut dungeon = two_rooms();
+        let first = dungeon.add_entity((0, 0), Entity::Switch(Switch {
+            triggers: Vec::new()
+        }));
+        let second = dungeon.add_entity((1, 0), Entity::Switch(Switch {
+            triggers: Vec::new()
+        }));
+
+        // Sets can depend on entities in other rooms
+        dungeon.rooms.get_mut(&offset(1, 0)).unwrap().trigger_sets.push(TriggerSet {
+            condition: Condition::All(vec![Condition::SwitchPressed(first), Condition::SwitchPressed(second)]),
+            triggers: vec![Trigger::Item(Item::Key(Key::Small))]
+        });
+
+        let mut state = State::new();
+        state.interact(&dungeon, offset(0, 0), first);
+        assert_eq!(state.small_keys, 0);
+
+        state.interact(&dungeon, offset(1, 0), second);
+        assert_eq!(state.small_keys, 1);
+
+        state.interact(&dungeon, offset(0, 0), first);
+        assert_eq!(state.small_keys, 1);
+
+    }
+
+
+    #[test]
+    fn one_way_doors_open_up_once_passed_from_their_far_side() {
+
+        let dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+            ((0, 0), (1, 0), DoorLock::OneWay(Offset { x: 1, y: 0 }))
+        ]);
+
+        let door = dungeon.door_between(offset(0, 0), offset(1, 0)).unwrap().id;
+        let mut state = State::new();
+        assert!(state.is_door_open(&dungeon, offset(1, 0), door));
+        assert!(state.is_door_open(&dungeon, offset(0, 0), door) == false);
+
+        // Walking through it from the far side opens it for good
+        state.enter_room(&dungeon, offset(1, 0), offset(0, 0));
+        assert!(state.is_door_open(&dungeon, offset(0, 0), door));
+        assert_eq!(state.take_changes(), vec![Change::Opened(door)]);
+
+    }
+
+
+    #[test]
+    fn dark_rooms_need_a_lantern_or_their_torches_lit() {
+
+        let mut dungeon = two_rooms();
+        dungeon.rooms.get_mut(&offset(1, 0)).unwrap().dark = true;
+        let torch = dungeon.add_entity((1, 0), Entity::Torch(Torch {
+            lit: false
+        }));
+        let chest = dungeon.add_entity((1, 0), Entity::Chest(Chest {
+            item: Item::Currency(50)
+        }));
+
+        // Torches can be lit in the dark, everything else has to wait
+        let mut state = State::new();
+        assert!(state.is_room_lit(&dungeon, offset(1, 0)) == false);
+        assert!(state.can_interact(&dungeon, offset(1, 0), chest) == false);
+        assert!(state.interact(&dungeon, offset(1, 0), chest) == false);
+        assert!(state.interact(&dungeon, offset(1, 0), torch));
+        assert!(state.is_room_lit(&dungeon, offset(1, 0)));
+        assert!(state.interact(&dungeon, offset(1, 0), chest));
+
+        let mut state = State::new();
+        state.items.push(Item::Lantern);
+        assert!(state.is_room_lit(&dungeon, offset(1, 0)));
+
+    }
+
+
+    #[test]
+    fn water_level_switches_can_be_pressed_again() {
+
+        let mut dungeon = Dungeon::from_rooms(&[(0, 0), (1, 0)], &[
+            ((0, 0), (1, 0), DoorLock::Water(1))
+        ]);
+        let raise = dungeon.add_entity((0, 0), Entity::Switch(Switch {
+            triggers: vec![Trigger::SetWaterLevel(1)]
+        }));
+        let lower = dungeon.add_entity((0, 0), Entity::Switch(Switch {
+            triggers: vec![Trigger::SetWaterLevel(0)]
+        }));
+
+        let door = dungeon.door_between(offset(0, 0), offset(1, 0)).unwrap().id;
+        let mut state = State::new();
+        assert!(state.is_door_open(&dungeon, offset(0, 0), door) == false);
+
+        // Pressing a switch for the current level changes nothing
+        assert!(state.interact(&dungeon, offset(0, 0), raise));
+        assert!(state.is_door_open(&dungeon, offset(0, 0), door));
+        assert!(state.interact(&dungeon, offset(0, 0), raise) == false);
+
+        assert!(state.interact(&dungeon, offset(0, 0), lower));
+        assert!(state.is_door_open(&dungeon, offset(0, 0), door) == false);
+        assert!(state.interact(&dungeon, offset(0, 0), raise));
+        assert_eq!(state.environment.water_level, 1);
+
+    }
+
+}
@@ -0,0 +1,36 @@
+extern crate dungeon;
+
+use dungeon::Dungeon;
+use dungeon::batch::{Batch, Error};
+
+#[test]
+fn a_panicking_seed_is_reported_without_losing_the_others() {
+
+    let seeds: Vec<Vec<usize>> = (0..6).map(|i| vec![i, 1, 2, 3]).collect();
+    let results = Batch::with(seeds, 2, |seed| {
+        match seed[0] {
+            2 => panic!("broken seed"),
+            3 => None,
+            _ => Dungeon::from_seed(seed, 12, 10)
+        }
+
+    }).collect_ordered();
+
+    assert_eq!(results.len(), 6);
+    for generated in results.iter() {
+        match generated.index {
+            2 => assert_eq!(generated.dungeon.as_ref().err(), Some(&Error::Panicked("broken seed".to_owned()))),
+            3 => assert_eq!(generated.dungeon.as_ref().err(), Some(&Error::Failed)),
+            _ => assert!(generated.dungeon.is_ok())
+        }
+    }
+
+}
+
+#[test]
+fn dungeons_report_the_try_they_were_generated_on() {
+    for seed in 0..10 {
+        let dungeon = Dungeon::from_seed(&[seed, 5, 5, 5], 19, 10).unwrap();
+        assert!(dungeon.tries() >= 1 && dungeon.tries() <= 10);
+    }
+}
@@ -0,0 +1,31 @@
+extern crate dungeon;
+
+use dungeon::Dungeon;
+use dungeon::base::Side;
+
+#[test]
+fn doors_connect_both_of_their_rooms() {
+    for seed in 0..10 {
+
+        let dungeon = Dungeon::from_seed(&[seed, 8, 1, 5], 25, 10).unwrap();
+        for (id, door) in dungeon.doors.iter() {
+
+            let (a, b) = door.rooms;
+            let from_a = dungeon.rooms[&a].connection_to(&b).unwrap();
+            let from_b = dungeon.rooms[&b].connection_to(&a).unwrap();
+
+            // Both sides share the door and face each other
+            assert_eq!(from_a.door, *id);
+            assert_eq!(from_b.door, *id);
+            assert_eq!(from_a.side, Side::from_offsets(&a, &b));
+            assert_eq!(from_b.side, Side::from_offsets(&b, &a));
+            assert_eq!(dungeon.door_between(a, b).unwrap().id, dungeon.door_between(b, a).unwrap().id);
+
+        }
+
+        // And every connection belongs to exactly one of those doors
+        let connections: usize = dungeon.rooms.values().map(|room| room.doors.len()).sum();
+        assert_eq!(connections, dungeon.doors.len() * 2);
+
+    }
+}
@@ -0,0 +1,18 @@
+extern crate dungeon;
+
+use dungeon::Dungeon;
+use dungeon::evolution::Layout;
+
+#[test]
+fn evolved_dungeons_stay_solvable() {
+
+    let best = Dungeon::evolve(&[3, 1, 4, 1], 20, 6, 8, |_, metrics| {
+        metrics.loops as f32 - metrics.average_key_distance()
+
+    }).expect("evolution should produce a dungeon");
+
+    // Offspring are mutated in place, so their layout has to follow along
+    assert!(best.dungeon.validate());
+    assert_eq!(best.layout, Layout::from_dungeon(&best.dungeon));
+
+}
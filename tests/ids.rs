@@ -0,0 +1,102 @@
+extern crate dungeon;
+
+use std::collections::HashSet;
+
+use dungeon::Dungeon;
+use dungeon::entity::Entity;
+use dungeon::entity::trigger::{Trigger, Condition};
+
+fn check_condition(dungeon: &Dungeon, condition: &Condition) {
+    match *condition {
+        Condition::EnemiesDefeated(room) | Condition::TorchesLit(room) => {
+            assert!(dungeon.room(room).is_some());
+        },
+        Condition::SwitchPressed(id) | Condition::ChestOpened(id) => {
+            assert!(dungeon.entity(id).is_some());
+        },
+        Condition::All(ref conditions) | Condition::Any(ref conditions) => {
+            for condition in conditions.iter() {
+                check_condition(dungeon, condition);
+            }
+        }
+    }
+}
+
+fn check_triggers(dungeon: &Dungeon, triggers: &[Trigger]) {
+    for trigger in triggers.iter() {
+        match *trigger {
+            Trigger::LockDoor(id) | Trigger::OpenDoor(id) => assert!(dungeon.door(id).is_some()),
+            Trigger::Warp(id) => match dungeon.entity(id) {
+                Some(&Entity::Warp(_)) => {},
+                entity => panic!("warp trigger references {:?}", entity)
+            },
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn rooms_doors_and_entities_are_found_by_their_ids() {
+    for seed in 0..10 {
+
+        let dungeon = Dungeon::from_seed(&[seed, 3, 2, 1], 25, 10).unwrap();
+        let mut ids = HashSet::new();
+        for (offset, room) in dungeon.rooms.iter() {
+
+            assert!(ids.insert(room.id.0));
+            assert_eq!(dungeon.room(room.id).unwrap().offset, *offset);
+
+            for &(id, ref entity) in room.entities.iter() {
+                assert!(ids.insert(id.0));
+                assert_eq!(dungeon.entity(id), Some(entity));
+            }
+
+        }
+
+        for (id, door) in dungeon.doors.iter() {
+            assert!(ids.insert(id.0));
+            assert_eq!(door.id, *id);
+        }
+
+    }
+}
+
+#[test]
+fn triggers_and_conditions_reference_existing_ids() {
+    for seed in 0..10 {
+
+        let dungeon = Dungeon::from_seed(&[seed, 3, 2, 1], 25, 10).unwrap();
+        for room in dungeon.rooms.values() {
+
+            for set in room.trigger_sets.iter() {
+                check_condition(&dungeon, &set.condition);
+                check_triggers(&dungeon, &set.triggers);
+            }
+
+            for connection in room.doors.iter() {
+                check_triggers(&dungeon, &connection.triggers);
+            }
+
+            for &(_, ref entity) in room.entities.iter() {
+                match *entity {
+                    Entity::Switch(ref switch) => check_triggers(&dungeon, &switch.triggers),
+                    Entity::Enemy(ref enemy) => check_triggers(&dungeon, &enemy.triggers),
+                    _ => {}
+                }
+            }
+
+        }
+
+    }
+}
+
+#[test]
+fn the_same_seed_assigns_the_same_ids() {
+    let a = Dungeon::from_seed(&[4, 3, 2, 1], 25, 10).unwrap();
+    let b = Dungeon::from_seed(&[4, 3, 2, 1], 25, 10).unwrap();
+    for (offset, room) in a.rooms.iter() {
+        let other = &b.rooms[offset];
+        assert_eq!(room.id, other.id);
+        assert_eq!(room.entities, other.entities);
+    }
+}
@@ -0,0 +1,42 @@
+extern crate dungeon;
+
+use dungeon::{Dungeon, State};
+use dungeon::base::Offset;
+use dungeon::entity::enemy::Type as EnemyType;
+
+fn door_states(dungeon: &Dungeon, state: &State, offset: Offset) -> Vec<bool> {
+    dungeon.rooms.get(&offset).unwrap().doors.iter().map(|d| {
+        state.is_door_open(dungeon, offset, d.door)
+
+    }).collect()
+}
+
+#[test]
+fn reentering_a_cleared_miniboss_room_keeps_its_doors_open() {
+
+    let dungeon = Dungeon::from_seed(&[1, 2, 3, 4], 25, 10).unwrap();
+    let (offset, enemy) = dungeon.rooms.values().filter_map(|room| {
+        room.enemies().into_iter().find(|&(_, enemy)| {
+            enemy.typ == EnemyType::Big
+
+        }).map(|(id, _)| (room.offset, id))
+
+    }).next().expect("seed should place a miniboss");
+
+    let from = dungeon.rooms.get(&offset).unwrap().doors[0].to;
+    let mut state = State::new();
+
+    state.enter_room(&dungeon, from, offset);
+    assert!(door_states(&dungeon, &state, offset).iter().all(|open| *open == false));
+
+    assert!(state.defeat_enemy(&dungeon, offset, enemy));
+    let cleared = door_states(&dungeon, &state, offset);
+    assert!(cleared.iter().any(|open| *open));
+
+    // Leave and come back in through the same door
+    state.enter_room(&dungeon, offset, from);
+    state.enter_room(&dungeon, from, offset);
+    assert_eq!(door_states(&dungeon, &state, offset), cleared);
+
+}
+
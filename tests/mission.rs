@@ -0,0 +1,101 @@
+extern crate dungeon;
+extern crate rand;
+
+use rand::{SeedableRng, StdRng};
+
+use dungeon::Dungeon;
+use dungeon::entity::item::Item;
+use dungeon::mission::{Mission, Node, Error};
+
+#[test]
+fn generated_missions_of_realistic_size_can_be_embedded() {
+    for seed in 0..20 {
+        let seed = [seed, 7, 3, 1];
+        let mut rng: StdRng = SeedableRng::from_seed(&seed[..]);
+        let mission = Mission::generate(&mut rng, 150);
+        if let Err(err) = Dungeon::from_mission(&seed, &mission, 300, 1) {
+            panic!("seed {:?}: {}", seed, err.to_string());
+        }
+    }
+}
+
+
+#[test]
+fn generated_missions_can_be_solved() {
+    for seed in 0..200 {
+        let mut rng: StdRng = SeedableRng::from_seed(&[seed, 3, 5][..]);
+        for &size in &[10, 30, 80] {
+            let mission = Mission::generate(&mut rng, size);
+            assert_eq!(mission.check(1000), Ok(()));
+        }
+    }
+}
+
+#[test]
+fn missions_with_more_parents_than_nodes_are_rejected() {
+    let mut mission = minimal();
+    mission.parents.push(Some(0));
+    assert_eq!(mission.check(100), Err(Error::MismatchedParents(5, 6)));
+}
+
+#[test]
+fn locks_need_a_key_outside_of_them() {
+
+    // A key behind its own lock can never be reached
+    let mut mission = minimal();
+    let lock = mission.add(0, Node::Lock);
+    mission.add(lock, Node::Key);
+    assert_eq!(mission.check(100), Err(Error::MissingKey(lock)));
+
+    // Neither can two keys which are locked behind each other
+    let second = mission.add(0, Node::Lock);
+    mission.add(second, Node::Key);
+    assert_eq!(mission.check(100), Err(Error::MissingKey(lock)));
+
+    // But one in front of them can
+    mission.add(0, Node::Key);
+    assert_eq!(mission.check(100), Ok(()));
+
+}
+
+#[test]
+fn gates_need_their_item_outside_of_them() {
+
+    let mut mission = minimal();
+    let gate = mission.add(0, Node::Gate(Item::Bow));
+    mission.add(gate, Node::Item(Item::Bow));
+    assert_eq!(mission.check(100), Err(Error::MissingItem(gate)));
+
+    mission.add(0, Node::Item(Item::Hookshot));
+    assert_eq!(mission.check(100), Err(Error::MissingItem(gate)));
+
+    mission.add(1, Node::Item(Item::Bow));
+    assert_eq!(mission.check(100), Ok(()));
+
+}
+
+#[test]
+fn the_boss_key_must_be_in_front_of_the_boss() {
+    let mut mission = Mission::new();
+    let boss = mission.add(0, Node::Boss);
+    let exit = mission.add(boss, Node::Exit);
+    mission.add(exit, Node::BossKey);
+    assert_eq!(mission.check(100), Err(Error::MissingBossKey));
+
+    let mut mission = Mission::new();
+    let gate = mission.add(0, Node::Gate(Item::Bombs));
+    mission.add(gate, Node::BossKey);
+    let boss = mission.add(0, Node::Boss);
+    mission.add(boss, Node::Exit);
+    assert_eq!(mission.check(100), Err(Error::MissingItem(gate)));
+}
+
+// Entrance -> Room -> (BossKey, Boss -> Exit)
+fn minimal() -> Mission {
+    let mut mission = Mission::new();
+    let hub = mission.add(0, Node::Room);
+    mission.add(hub, Node::BossKey);
+    let boss = mission.add(hub, Node::Boss);
+    mission.add(boss, Node::Exit);
+    mission
+}
@@ -0,0 +1,50 @@
+extern crate dungeon;
+
+use std::time::Duration;
+
+use dungeon::Dungeon;
+use dungeon::base::sorted_offsets;
+use dungeon::session::{Session, Phase, Error};
+
+#[test]
+fn sessions_report_their_phases_and_generate_the_seeded_dungeon() {
+
+    let session = Session::new(&[3, 1, 4, 1], 19, 10);
+    let mut phases = Vec::new();
+    let dungeon = session.run(|phase, tries| phases.push((phase, tries))).unwrap();
+
+    assert_eq!(phases.first(), Some(&(Phase::Layout, 1)));
+    assert_eq!(phases.last().map(|&(_, tries)| tries), Some(dungeon.tries()));
+    for phase in [Phase::SpecialRooms, Phase::Locks, Phase::Keys, Phase::Features, Phase::Validation].iter() {
+        assert!(phases.iter().any(|&(p, _)| p == *phase));
+    }
+
+    let plain = Dungeon::from_seed(&[3, 1, 4, 1], 19, 10).unwrap();
+    assert_eq!(sorted_offsets(dungeon.rooms.keys().cloned()), sorted_offsets(plain.rooms.keys().cloned()));
+
+}
+
+#[test]
+fn sessions_can_be_cancelled_while_running() {
+
+    let session = Session::new(&[3, 1, 4, 1], 19, 10);
+    let cancel = session.cancel.clone();
+    let mut phases = Vec::new();
+    let result = session.run(|phase, _| {
+        phases.push(phase);
+        if phase == Phase::Keys {
+            cancel.cancel();
+        }
+    });
+
+    assert_eq!(result.err(), Some(Error::Cancelled));
+    assert_eq!(phases.last(), Some(&Phase::Keys));
+
+}
+
+#[test]
+fn sessions_stop_once_their_budget_is_used_up() {
+    let mut session = Session::new(&[3, 1, 4, 1], 19, 10);
+    session.budget = Some(Duration::from_secs(0));
+    assert_eq!(session.run(|_, _| {}).err(), Some(Error::OutOfTime));
+}
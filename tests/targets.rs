@@ -0,0 +1,56 @@
+extern crate dungeon;
+
+use std::f32;
+use std::usize;
+use std::time::Duration;
+
+use dungeon::Dungeon;
+use dungeon::metrics::{Targets, Error};
+
+fn rooms(min: usize, max: usize) -> Targets {
+    Targets {
+        rooms: Some((min, max)),
+        .. Targets::default()
+    }
+}
+
+#[test]
+fn targets_with_inverted_ranges_are_rejected() {
+
+    let undefined = Targets {
+        key_distance: Some((f32::NAN, 4.0)),
+        .. Targets::default()
+    };
+
+    assert!(Targets::default().is_valid());
+    assert_eq!(rooms(20, 10).check(), Err(Error::InvalidRange("rooms")));
+    assert_eq!(undefined.check(), Err(Error::InvalidRange("key distance")));
+    assert_eq!(
+        Dungeon::from_targets(&[1, 2, 3, 4], &rooms(20, 10), 10, Duration::from_secs(5)).err(),
+        Some(Error::InvalidRange("rooms"))
+    );
+
+}
+
+#[test]
+fn room_ranges_outside_of_the_generated_sizes_are_rejected() {
+
+    assert_eq!(rooms(0, 2).check(), Err(Error::TooFewRooms(2)));
+    assert_eq!(
+        Dungeon::from_targets(&[1, 2, 3, 4], &rooms(usize::MAX / 2 + 1, usize::MAX), 10, Duration::from_secs(5)).err(),
+        Some(Error::TooManyRooms(usize::MAX / 2 + 1))
+    );
+
+}
+
+#[test]
+fn room_ranges_with_a_small_minimum_still_produce_dungeons() {
+
+    for &(min, max) in [(0, 30), (1, 30)].iter() {
+        let candidate = Dungeon::from_targets(&[1, 2, 3, 4], &rooms(min, max), 5, Duration::from_secs(30));
+        let candidate = candidate.expect("targets should produce a dungeon");
+        assert!(candidate.metrics.rooms >= Dungeon::MIN_ROOM_COUNT);
+        assert!(candidate.metrics.rooms <= max);
+    }
+
+}
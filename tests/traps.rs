@@ -0,0 +1,44 @@
+extern crate dungeon;
+
+use dungeon::entity::trap::Type as TrapType;
+use dungeon::session::{Session, Difficulty};
+
+// Number of traps, their total damage and the number of blades across a few
+// dungeons generated with the same seeds
+fn traps(difficulty: Difficulty) -> (usize, usize, usize) {
+
+    let mut totals = (0, 0, 0);
+    for seed in 0..10 {
+
+        let mut session = Session::new(&[seed, 4, 2, 1], 25, 10);
+        session.difficulty = difficulty;
+
+        let dungeon = session.run(|_, _| {}).unwrap();
+        for room in dungeon.rooms.values() {
+            for &(_, trap) in room.traps().iter() {
+                totals.0 += 1;
+                totals.1 += trap.damage;
+                if trap.typ == TrapType::Blade {
+                    totals.2 += 1;
+                }
+            }
+        }
+
+    }
+
+    totals
+
+}
+
+#[test]
+fn harder_dungeons_contain_more_and_harder_traps() {
+
+    let easy = traps(Difficulty::Easy);
+    let normal = traps(Difficulty::Normal);
+    let hard = traps(Difficulty::Hard);
+
+    assert!(easy.0 < normal.0 && normal.0 < hard.0, "{:?} {:?} {:?}", easy, normal, hard);
+    assert!(easy.1 < normal.1 && normal.1 < hard.1, "{:?} {:?} {:?}", easy, normal, hard);
+    assert_eq!(easy.2, 0);
+
+}